@@ -7,6 +7,8 @@ use serde_json::from_reader as read_config;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Limit {
+    #[serde(default)]
+    pub min: u32,
     pub max: u32,
     pub items: Vec<u32>
 }
@@ -27,7 +29,12 @@ pub struct ReplacedItem {
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct ReplacedEnchantment {
     pub slot: String,
-    pub id: u32
+    #[serde(default)]
+    pub id: u32,
+    #[serde(default)]
+    pub candidates: Vec<u32>,
+    #[serde(default)]
+    pub gems: Vec<u32>
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -46,13 +53,147 @@ pub struct Simcraft {
     pub json: String,
     pub override_spec: bool,
     pub override_talents: bool,
-    pub replace_weaponhand_enum: bool
+    pub replace_weaponhand_enum: bool,
+    #[serde(default = "default_jobs")]
+    pub jobs: usize,
+    #[serde(default)]
+    pub profileset_size: usize,
+    #[serde(default)]
+    pub talents: Vec<String>,
+    #[serde(default)]
+    pub override_seed: bool,
+    #[serde(default = "default_true")]
+    pub calibrate: bool,
+    #[serde(default)]
+    pub calibration_cache: String,
+    #[serde(default = "default_checkpoint_interval")]
+    pub checkpoint_interval: u64
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Statistic {
     pub tolerance: f32,
-    pub threshold: i32
+    pub threshold: i32,
+    #[serde(default)]
+    pub confidence: f32,
+    #[serde(default)]
+    pub margin: f32,
+    #[serde(default = "default_strategy")]
+    pub strategy: String,
+    #[serde(default = "default_percentile")]
+    pub percentile: f32,
+    #[serde(default = "default_rank")]
+    pub rank: usize,
+    #[serde(default)]
+    pub min_seen: i32,
+    #[serde(default = "default_decay")]
+    pub decay: f32
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Stage {
+    pub keep: f32,
+    #[serde(default)]
+    pub options: Vec<String>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Genetic {
+    pub population: usize,
+    pub generations: usize,
+    pub max_sims: u64,
+    pub mutation_rate: f32,
+    #[serde(default)]
+    pub elite: usize
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Set {
+    pub name: String,
+    #[serde(default)]
+    pub min: u32,
+    #[serde(default = "default_max")]
+    pub max: u32,
+    pub items: Vec<u32>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Restriction {
+    #[serde(default)]
+    pub lock: Vec<String>,
+    #[serde(default)]
+    pub only: Vec<String>,
+    #[serde(default)]
+    pub exclude_items: Vec<u32>,
+    #[serde(default)]
+    pub include_items: Vec<u32>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Constraint {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub items: Vec<u32>,
+    #[serde(default)]
+    pub slots: Vec<String>,
+    #[serde(default)]
+    pub then: Vec<u32>,
+    #[serde(default)]
+    pub min: u32,
+    #[serde(default = "default_max")]
+    pub max: u32
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UniqueItem {
+    pub id: u32,
+    pub unique: bool
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UniqueCategory {
+    pub name: String,
+    pub max: u32,
+    pub items: Vec<u32>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Unique {
+    #[serde(default = "default_true")]
+    pub by_default: bool,
+    #[serde(default)]
+    pub items: Vec<UniqueItem>,
+    #[serde(default)]
+    pub categories: Vec<UniqueCategory>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Weapons {
+    #[serde(default)]
+    pub two_hand: Vec<u32>,
+    #[serde(default)]
+    pub off_hand: Vec<u32>,
+    #[serde(default)]
+    pub titans_grip: bool,
+    #[serde(default)]
+    pub ordered: bool
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Scheduler {
+    #[serde(default = "default_mode")]
+    pub mode: String,
+    #[serde(default = "default_exploration")]
+    pub exploration: f32,
+    #[serde(default = "default_pool")]
+    pub pool: usize
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Azerite {
+    pub id: u32,
+    pub rings: Vec<Vec<u32>>
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -65,13 +206,108 @@ pub struct Configuration
     pub simcraft: Simcraft,
     pub replaces: Replacement,
     pub limits: Vec<Limit>,
-    pub statistic: Statistic
+    pub statistic: Statistic,
+    #[serde(default)]
+    pub stages: Vec<Stage>,
+    #[serde(default = "default_search")]
+    pub search: String,
+    #[serde(default)]
+    pub genetic: Option<Genetic>,
+    #[serde(default)]
+    pub azerite: Vec<Azerite>,
+    #[serde(default)]
+    pub weapons: Weapons,
+    #[serde(default)]
+    pub unique: Unique,
+    #[serde(default)]
+    pub sets: Vec<Set>,
+    #[serde(default)]
+    pub constraints: Vec<Constraint>,
+    #[serde(default)]
+    pub restrict: Restriction,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub scheduler: Scheduler,
+    #[serde(default)]
+    pub warm_start: Option<String>
 }
 
 
 pub const CONFIG_FILE: &str = "config.json";
 
 
+fn default_jobs() -> usize {
+    1
+}
+
+fn default_search() -> String {
+    String::from("permutation")
+}
+
+fn default_strategy() -> String {
+    String::from("rating")
+}
+
+fn default_percentile() -> f32 {
+    25.0
+}
+
+fn default_rank() -> usize {
+    3
+}
+
+fn default_mode() -> String {
+    String::from("random")
+}
+
+fn default_exploration() -> f32 {
+    1.0
+}
+
+fn default_pool() -> usize {
+    100
+}
+
+fn default_decay() -> f32 {
+    0.5
+}
+
+fn default_checkpoint_interval() -> u64 {
+    60
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max() -> u32 {
+    u32::MAX
+}
+
+
+// Without a configuration every item is unique-equipped
+impl Default for Unique {
+    fn default() -> Unique {
+        Unique {
+            by_default: true,
+            items: Vec::new(),
+            categories: Vec::new()
+        }
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Scheduler {
+        Scheduler {
+            mode: default_mode(),
+            exploration: default_exploration(),
+            pool: default_pool()
+        }
+    }
+}
+
+
 impl Configuration {
     pub fn load(file: &str) -> Result<Configuration, Error> {
         match File::open(file) {
@@ -84,6 +320,23 @@ impl Configuration {
                     }
                 };
 
+                // a crossover needs two parents
+                if let Some(ref genetic) = config.genetic {
+                    if genetic.population < 2 || genetic.generations < 1 {
+                        return Err(Error::new(ErrorKind::InvalidData,
+                            format!("The genetic search needs a population of at least 2 and at least 1 generation, got {} and {}",
+                                genetic.population, genetic.generations)));
+                    }
+                }
+
+                // weapon types are only known for the pooled weapon slot.
+                // Separate main and off hand slots would ignore them.
+                let weapons = &config.weapons;
+                if (!weapons.two_hand.is_empty() || !weapons.off_hand.is_empty()) && !config.simcraft.replace_weaponhand_enum {
+                    return Err(Error::new(ErrorKind::InvalidData,
+                        "weapons.two_hand and weapons.off_hand need simcraft.replace_weaponhand_enum"));
+                }
+
                 Ok(config)
             },
             Err(err) => {
@@ -30,7 +30,7 @@ const AUTHOR: &str = "[DM]Origin";
 // This is the time in seconds in which one iteration
 // runs. On faster machines its about 12s to 15s. But on
 // slower one it could be of around 25s.
-// So I set this to 19s. I hope this is a god value :)
+// It is only used if the calibration is disabled or fails.
 const TIME_PER_ITER: u64 = 15;
 
 
@@ -56,8 +56,65 @@ fn main() {
         .arg(Arg::with_name("talents")
             .short("t")
             .long("talents")
+            .value_name("TALENTS")
             .takes_value(true)
-            .help("Override the talent setting from input file."))
+            .help("Override the talent setting from input file. Several talent builds are separated by comma."))
+        .arg(Arg::with_name("jobs")
+            .short("j")
+            .long("jobs")
+            .value_name("N")
+            .takes_value(true)
+            .help("Number of simc processes that run at the same time."))
+        .arg(Arg::with_name("search")
+            .long("search")
+            .value_name("MODE")
+            .takes_value(true)
+            .possible_values(&["permutation", "genetic", "upgrade"])
+            .help("Override the search mode from the configuration."))
+        .arg(Arg::with_name("resume")
+            .long("resume")
+            .value_name("RUN_DIR")
+            .takes_value(true)
+            .help("Continue an interrupted run stored in this output directory."))
+        .arg(Arg::with_name("warm-start")
+            .long("warm-start")
+            .value_name("RUN_DIR")
+            .takes_value(true)
+            .help("Start with the item statistic of an earlier run stored in this output directory."))
+        .arg(Arg::with_name("seed")
+            .long("seed")
+            .value_name("SEED")
+            .takes_value(true)
+            .help("Seed of the random order. Use it to repeat a run."))
+        .arg(Arg::with_name("lock")
+            .long("lock")
+            .value_name("SLOTS")
+            .takes_value(true)
+            .help("Keep the equipped items of these slots, e.g. head,neck."))
+        .arg(Arg::with_name("only")
+            .long("only")
+            .value_name("SLOTS")
+            .takes_value(true)
+            .help("Permut only these slots and keep the equipped items of all others."))
+        .arg(Arg::with_name("exclude-item")
+            .long("exclude-item")
+            .value_name("ID")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .use_delimiter(true)
+            .help("Remove the item with this id from the permutation."))
+        .arg(Arg::with_name("include-item")
+            .long("include-item")
+            .value_name("ID")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .use_delimiter(true)
+            .help("Keep the item with this id, even if its slot is locked."))
+        .arg(Arg::with_name("dry-run")
+            .long("dry-run")
+            .help("Compile the simc profiles of all combinations without running simc."))
         .arg(Arg::with_name("yes")
             .short("y")
             .help("Accept automaticaly the amount of iterations."))
@@ -66,23 +123,77 @@ fn main() {
 
     // read config file
     let config_file = arg_matches.value_of("config").unwrap_or(CONFIG_FILE);
-    let config = configuration::Configuration::load(config_file).unwrap();
-    let talents = arg_matches.value_of("talents").unwrap_or("");
+    let mut config = configuration::Configuration::load(config_file).unwrap();
     let accept = arg_matches.is_present("yes");
+    let dry_run = arg_matches.is_present("dry-run");
+
+    if let Some(search) = arg_matches.value_of("search") {
+        config.search = String::from(search);
+    }
+
+    if let Some(jobs) = arg_matches.value_of("jobs") {
+        config.simcraft.jobs = jobs.parse::<usize>().expect("Value of jobs must be a number");
+    }
+
+    if let Some(run_dir) = arg_matches.value_of("warm-start") {
+        config.warm_start = Some(String::from(run_dir));
+    }
+
+    if let Some(seed) = arg_matches.value_of("seed") {
+        config.seed = Some(seed.parse::<u64>().expect("Value of seed must be a number"));
+    }
+
+    // restrictions from command line replace the ones of the configuration
+    if let Some(lock) = arg_matches.value_of("lock") {
+        config.restrict.lock = lock.split(',').map(String::from).collect();
+    }
+
+    if let Some(only) = arg_matches.value_of("only") {
+        config.restrict.only = only.split(',').map(String::from).collect();
+    }
+
+    if let Some(ids) = arg_matches.values_of("exclude-item") {
+        config.restrict.exclude_items = ids.map(|id| id.parse::<u32>().expect("Item id must be a number")).collect();
+    }
+
+    if let Some(ids) = arg_matches.values_of("include-item") {
+        config.restrict.include_items = ids.map(|id| id.parse::<u32>().expect("Item id must be a number")).collect();
+    }
+
+    // talents from command line replace the ones of the configuration
+    let talents: Vec<String> = match arg_matches.value_of("talents") {
+        Some(talents) => talents.split(',')
+            .map(|t| String::from(t.trim()))
+            .filter(|t| !t.is_empty())
+            .collect(),
+        None => config.simcraft.talents.clone()
+    };
 
     // Map for all items
     let item_list_file = arg_matches.value_of("INPUT").unwrap();
     println!("Read data from input file: {}", item_list_file);
 
     // handle simc
-    let mut simc = simcraft::Simcraft::new(&config, talents);
+    let mut simc = simcraft::Simcraft::new(&config, &talents, arg_matches.value_of("resume"));
     simc.compute_item_list(item_list_file).unwrap();
     
     // calculate the number of iterations
     println!("Calculate the number of iterations...");
-    let iterations = simc.calculate_iterations();
+    let (iterations, estimated) = simc.calculate_iterations();
     println!("Your request generates absolute {} iterations", iterations.0);
-    println!("This runs for approximalty: {}", fmt_duration(iterations.1 * TIME_PER_ITER));
+    if estimated {
+        println!("The statistic will probably need {} simulations", iterations.1);
+    } else {
+        println!("Without the statistic of an earlier run at most {} simulations are needed", iterations.1);
+    }
+    if !dry_run {
+        // measure the duration of a simulation on this machine
+        match simc.calibrate() {
+            Ok(seconds) => println!("A simulation takes approximatly {:.1}s", seconds),
+            Err(err) => println!("Calibration failed, assume {}s per simulation: {}", TIME_PER_ITER, err)
+        }
+        println!("This runs for approximalty: {}", fmt_duration(simc.estimate(iterations.1)));
+    }
     println!("Do you want to continue? (y == yes / n == no)");
 
     if accept == false {
@@ -104,7 +215,15 @@ fn main() {
     }
 
     // start permutation
-    simc.permutation(iterations).unwrap();
+    if dry_run {
+        simc.dry_run(iterations).unwrap();
+    } else if config.search == "genetic" {
+        simc.genetic(iterations).unwrap();
+    } else if config.search == "upgrade" {
+        simc.upgrade().unwrap();
+    } else {
+        simc.permutation(iterations).unwrap();
+    }
 }
 
 
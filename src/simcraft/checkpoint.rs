@@ -0,0 +1,103 @@
+use std::fs::{File, rename};
+use std::result::{Result};
+use std::io::{Error, ErrorKind};
+use serde_json::{from_reader as read_json, to_string as write_json};
+use permutation::Order;
+use statistic::Data;
+use report::{Report, Stage};
+use template::Template;
+
+
+pub const CHECKPOINT_FILE: &str = "checkpoint.json";
+
+
+/// A single simulated combination.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Done {
+    pub index: u64,
+    pub dps: f32,
+    #[serde(default)]
+    pub report: String
+}
+
+
+/// The state of a run. It is stored in the output directory at the
+/// end of every stage and after the configured interval. With it an
+/// interrupted run can be continued.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Checkpoint {
+    pub order: Option<Order>,
+    /// identifies the items and rules. Older checkpoints have none.
+    #[serde(default)]
+    pub fingerprint: String,
+    pub parse_counter: u64,
+    pub done: Vec<Done>,
+    pub statistic: Vec<Data>,
+    pub reports: Vec<Report>,
+    #[serde(default)]
+    pub stage: usize,
+    #[serde(default)]
+    pub stages: Vec<Vec<Done>>,
+    #[serde(default)]
+    pub stage_reports: Vec<Stage>,
+    #[serde(default)]
+    pub build: usize,
+    #[serde(default)]
+    pub builds: Vec<Stage>
+}
+
+impl Checkpoint {
+    pub fn new(order: &Order, fingerprint: &str) -> Checkpoint {
+        Checkpoint {
+            order: Some(*order),
+            fingerprint: String::from(fingerprint),
+            parse_counter: 0,
+            done: Vec::new(),
+            statistic: Vec::new(),
+            reports: Vec::new(),
+            stage: 0,
+            stages: Vec::new(),
+            stage_reports: Vec::new(),
+            build: 0,
+            builds: Vec::new()
+        }
+    }
+
+    pub fn load(output_dir: &str) -> Result<Checkpoint, Error> {
+        let file = format!("{}/{}", output_dir, CHECKPOINT_FILE);
+
+        match File::open(&file) {
+            Ok(json) => {
+                match read_json(json) {
+                    Ok(checkpoint) => Ok(checkpoint),
+                    Err(err) => {
+                        Err(Error::new(ErrorKind::InvalidData,
+                            format!("Cannot read checkpoint {}: {}", file, err)))
+                    }
+                }
+            },
+            Err(err) => {
+                Err(Error::new(ErrorKind::NotFound,
+                    format!("Failed to open checkpoint {}: {:?}", file, err)))
+            }
+        }
+    }
+
+    /// Write the checkpoint to disc. The data is written to a temporary
+    /// file first. So a crash while writing will not destroy the last one.
+    pub fn store(&self, output_dir: &str) -> Result<(), Error> {
+        let file = format!("{}/{}", output_dir, CHECKPOINT_FILE);
+        let tmp = format!("{}.tmp", file);
+
+        let json = match write_json(self) {
+            Ok(json) => json,
+            Err(err) => {
+                return Err(Error::new(ErrorKind::InvalidData,
+                    format!("Cannot write checkpoint: {}", err)));
+            }
+        };
+
+        Template::store(&tmp, &json)?;
+        rename(&tmp, &file)
+    }
+}
@@ -0,0 +1,256 @@
+use std::result::{Result};
+use std::io::{Error, ErrorKind};
+use configuration::{Configuration, Constraint};
+use item::Item;
+use item_map::ItemMap;
+use slot::Slot;
+
+
+/// Counts the items of a stack that match the ids and the slots. An
+/// empty list matches every item.
+struct Counter {
+    items: Vec<u32>,
+    slots: Vec<String>,
+    min: u32,
+    max: u32
+}
+
+impl Counter {
+    fn matches(&self, item: &Item) -> bool {
+        if !self.items.is_empty() && !self.items.contains(&item.id) {
+            return false;
+        }
+
+        if self.slots.is_empty() {
+            return true;
+        }
+
+        // a slot can be named by its position or by its kind, e.g.
+        // finger1 or finger
+        let names = [
+            item.slot.name.clone(),
+            item.slot.get_name(),
+            Slot::from_enum(Slot::fix_slot(item.slot.slot)).name
+        ];
+
+        names.iter().any(|n| self.slots.contains(n))
+    }
+}
+
+
+/// All rules of a run. Limits, unique categories, sets and constraints
+/// are compiled into counters. A stack is valid if every counter is
+/// inside its bounds and all implications are fulfilled.
+///
+/// The counters can be summed up item by item. So the number of valid
+/// combinations can be counted without building them.
+#[derive(Default)]
+pub struct Rules {
+    counters: Vec<Counter>,
+    implies: Vec<(usize, Vec<usize>)>
+}
+
+impl Rules {
+    /// Compile all rules of the configuration. Returns an error if a
+    /// constraint is invalid or the rules contradict each other.
+    pub fn new(config: &Configuration, items: &ItemMap) -> Result<Rules, Error> {
+        let mut rules = Rules::default();
+
+        for limit in &config.limits {
+            rules.push(&limit.items, &[], limit.min, limit.max);
+        }
+
+        for category in &config.unique.categories {
+            rules.push(&category.items, &[], 0, category.max);
+        }
+
+        for set in &config.sets {
+            rules.push(&set.items, &[], set.min, set.max);
+        }
+
+        // only pooled weapons are an unordered pair. Otherwise the main
+        // and the off hand are separate slots.
+        let weapon_pair = config.simcraft.replace_weaponhand_enum && !config.weapons.ordered;
+
+        let mut required: Vec<u32> = Vec::new();
+        for (n, constraint) in config.constraints.iter().enumerate() {
+            rules.add(n + 1, constraint, items, weapon_pair)?;
+
+            if constraint.kind == "required" && constraint.slots.is_empty() {
+                required.extend(constraint.items.iter());
+            }
+        }
+
+        rules.check_required(&required)?;
+
+        Ok(rules)
+    }
+
+    /// Number of counters.
+    pub fn size(&self) -> usize {
+        self.counters.len()
+    }
+
+    /// The value an item adds to every counter.
+    pub fn usage(&self, item: &Item) -> Vec<u32> {
+        self.counters.iter()
+            .map(|c| if c.matches(item) { 1 } else { 0 })
+            .collect()
+    }
+
+    /// Is a counter already over its maximum? More items can not fix this.
+    pub fn over_max(&self, counts: &[u32]) -> bool {
+        counts.iter().zip(self.counters.iter()).any(|(n, c)| *n > c.max)
+    }
+
+    /// Can a counter not reach its minimum anymore? remaining is the most
+    /// all following items can add to each counter.
+    pub fn out_of_reach(&self, counts: &[u32], remaining: &[u32]) -> bool {
+        counts.iter().zip(remaining.iter()).zip(self.counters.iter())
+            .any(|((n, r), c)| n + r < c.min)
+    }
+
+    /// Are all rules fulfilled by these counters?
+    pub fn accepts(&self, counts: &[u32]) -> bool {
+        let bounds = counts.iter().zip(self.counters.iter())
+            .all(|(n, c)| *n >= c.min && *n <= c.max);
+
+        bounds && self.implies.iter().all(|(condition, then)| {
+            counts[*condition] == 0 || then.iter().all(|t| counts[*t] > 0)
+        })
+    }
+
+    /// Returns true if a stack breaks any rule.
+    pub fn breaks(&self, stack: &[Item]) -> bool {
+        let mut counts: Vec<u32> = vec![0; self.counters.len()];
+        for item in stack.iter() {
+            for (count, usage) in counts.iter_mut().zip(self.usage(item).iter()) {
+                *count += usage;
+            }
+        }
+
+        !self.accepts(&counts)
+    }
+
+    fn push(&mut self, items: &[u32], slots: &[String], min: u32, max: u32) -> usize {
+        self.counters.push(Counter {
+            items: items.to_vec(),
+            slots: slots.to_vec(),
+            min,
+            max
+        });
+
+        self.counters.len() - 1
+    }
+
+    /// Compile a single constraint. n is the position in the configuration
+    /// and is only used for error messages.
+    fn add(&mut self, n: usize, constraint: &Constraint, items: &ItemMap, weapon_pair: bool) -> Result<(), Error> {
+        for slot in constraint.slots.iter() {
+            if Slot::from_str(slot).is_err() {
+                return Err(Rules::error(n, &format!("unknown slot {}", slot)));
+            }
+
+            // rings and trinkets are combined as unordered pairs. An item is
+            // only placed at one position of the pair. So a position would
+            // drop valid combinations. Pooled weapons are ordered on request.
+            match slot.as_str() {
+                "finger1" | "finger2" | "trinket1" | "trinket2" => {
+                    return Err(Rules::error(n, &format!(
+                        "slot {} is a position of a pair. Pairs have no order, use {}", slot, slot.trim_end_matches(char::is_numeric))));
+                },
+                "weaponhand1" | "weaponhand2" | "main_hand" | "off_hand" if weapon_pair => {
+                    return Err(Rules::error(n, &format!(
+                        "slot {} is a position of a pair. Use weaponhand or set weapons.ordered", slot)));
+                },
+                _ => ()
+            }
+        }
+
+        let selects = !constraint.items.is_empty() || !constraint.slots.is_empty();
+
+        match constraint.kind.as_str() {
+            "required" => {
+                if constraint.items.is_empty() {
+                    return Err(Rules::error(n, "required needs items"));
+                }
+
+                // every required item must be there
+                for id in constraint.items.iter() {
+                    if !items.contains(*id) {
+                        return Err(Rules::error(n, &format!("required item {} is not in the input", id)));
+                    }
+
+                    self.push(&[*id], &constraint.slots, 1, u32::MAX);
+                }
+            },
+            "forbidden" => {
+                if !selects {
+                    return Err(Rules::error(n, "forbidden needs items or slots"));
+                }
+
+                self.push(&constraint.items, &constraint.slots, 0, 0);
+            },
+            "exclusive" => {
+                if !selects {
+                    return Err(Rules::error(n, "exclusive needs items or slots"));
+                }
+
+                self.push(&constraint.items, &constraint.slots, 0, 1);
+            },
+            "count" => {
+                if !selects {
+                    return Err(Rules::error(n, "count needs items or slots"));
+                }
+
+                if constraint.min > constraint.max {
+                    return Err(Rules::error(n, &format!("min {} is greater than max {}",
+                        constraint.min, constraint.max)));
+                }
+
+                self.push(&constraint.items, &constraint.slots, constraint.min, constraint.max);
+            },
+            "implies" => {
+                if constraint.items.is_empty() || constraint.then.is_empty() {
+                    return Err(Rules::error(n, "implies needs items and then"));
+                }
+
+                let condition = self.push(&constraint.items, &constraint.slots, 0, u32::MAX);
+                let then: Vec<usize> = constraint.then.iter()
+                    .map(|id| self.push(&[*id], &[], 0, u32::MAX))
+                    .collect();
+
+                self.implies.push((condition, then));
+            },
+            kind => {
+                return Err(Rules::error(n, &format!(
+                    "unknown type {}. Use required, forbidden, implies, exclusive or count", kind)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Required items must fit into the maximum of every counter.
+    /// Otherwise no combination can fulfill all rules.
+    fn check_required(&self, required: &[u32]) -> Result<(), Error> {
+        for counter in self.counters.iter().filter(|c| c.slots.is_empty()) {
+            let needed: Vec<u32> = required.iter()
+                .filter(|id| counter.items.is_empty() || counter.items.contains(id))
+                .cloned()
+                .collect();
+
+            if needed.len() as u32 > counter.max {
+                return Err(Error::new(ErrorKind::InvalidData,
+                    format!("Contradicting rules: required items {:?} exceed the maximum of {} for items {:?}",
+                        needed, counter.max, counter.items)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn error(n: usize, message: &str) -> Error {
+        Error::new(ErrorKind::InvalidData, format!("Constraint {}: {}", n, message))
+    }
+}
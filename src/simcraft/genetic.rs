@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use rand::Rng;
+use configuration::Genetic;
+use permutation::Permutation;
+
+
+// Number of tries to find a valid combination. If a child breaks
+// the rules, it will be mutated again.
+const MAX_TRIES: usize = 1000;
+
+
+/// Operators of the genetic search. An individual is the index of a
+/// combination. Every step of the permutation is one gene. So crossover
+/// and mutation work per slot and the pair rules of rings, trinkets
+/// and weapons are still given.
+pub struct Evolution<'a> {
+    permutation: &'a Permutation<'a>,
+    config: &'a Genetic,
+    sizes: Vec<u64>
+}
+
+impl<'a> Evolution<'a> {
+    pub fn new(permutation: &'a Permutation<'a>, config: &'a Genetic) -> Evolution<'a> {
+        Evolution {
+            permutation,
+            config,
+            sizes: permutation.step_sizes()
+        }
+    }
+
+    /// Create a random population. Every individual is a valid combination.
+    pub fn populate<R: Rng>(&self, rng: &mut R) -> Vec<u64> {
+        let mut population: Vec<u64> = Vec::new();
+
+        for _ in 0..MAX_TRIES * self.config.population {
+            if population.len() >= self.config.population {
+                break;
+            }
+
+            let index = rng.gen_range(0, self.permutation.size());
+            if !population.contains(&index) && self.permutation.get(index).is_some() {
+                population.push(index);
+            }
+        }
+
+        population
+    }
+
+    /// Build the next generation. The best ones are taken as they are.
+    /// All others are children of two parents, selected by tournament.
+    /// If no child is valid, the parents stay.
+    pub fn evolve<R: Rng>(&self, rng: &mut R, population: &[u64], fitness: &HashMap<u64, f32>) -> Vec<u64> {
+        let mut sorted: Vec<u64> = population.to_vec();
+        sorted.sort_by(|a, b| Evolution::fitness_of(fitness, *b)
+            .partial_cmp(&Evolution::fitness_of(fitness, *a)).unwrap());
+
+        let mut next: Vec<u64> = sorted.iter().take(self.config.elite).cloned().collect();
+
+        for _ in 0..MAX_TRIES * self.config.population {
+            if next.len() >= self.config.population {
+                break;
+            }
+
+            let first = Evolution::select(rng, &sorted, fitness);
+            let second = Evolution::select(rng, &sorted, fitness);
+            let genes = self.crossover(rng, first, second);
+            let child = self.mutate(rng, &genes);
+
+            if self.permutation.get(child).is_some() {
+                next.push(child);
+            }
+        }
+
+        if next.is_empty() {
+            return sorted;
+        }
+
+        next
+    }
+
+    /// Take every gene from one of both parents.
+    fn crossover<R: Rng>(&self, rng: &mut R, first: u64, second: u64) -> Vec<u64> {
+        let first = self.permutation.digits(first);
+        let second = self.permutation.digits(second);
+
+        first.iter().zip(second.iter())
+            .map(|(a, b)| if rng.gen::<bool>() { *a } else { *b })
+            .collect()
+    }
+
+    /// Replace every gene with a random one by the configured rate.
+    fn mutate<R: Rng>(&self, rng: &mut R, digits: &[u64]) -> u64 {
+        let mut digits: Vec<u64> = digits.to_vec();
+
+        for (digit, size) in digits.iter_mut().zip(self.sizes.iter()) {
+            if rng.gen::<f32>() < self.config.mutation_rate {
+                *digit = rng.gen_range(0, *size);
+            }
+        }
+
+        self.permutation.index(&digits)
+    }
+
+    /// Tournament selection with two individuals.
+    fn select<R: Rng>(rng: &mut R, population: &[u64], fitness: &HashMap<u64, f32>) -> u64 {
+        let first = population[rng.gen_range(0, population.len())];
+        let second = population[rng.gen_range(0, population.len())];
+
+        if Evolution::fitness_of(fitness, first) >= Evolution::fitness_of(fitness, second) {
+            first
+        } else {
+            second
+        }
+    }
+
+    fn fitness_of(fitness: &HashMap<u64, f32>, index: u64) -> f32 {
+        match fitness.get(&index) {
+            Some(dps) => *dps,
+            None => 0.0
+        }
+    }
+}
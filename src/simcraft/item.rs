@@ -15,7 +15,10 @@ pub struct Item
     pub relic_id: String,
     pub enchant_id: u32,
     pub azerite_powers: String,
-    pub azerite_level: u32
+    pub azerite_level: u32,
+    pub equipped: bool,
+    pub origin: usize,
+    variant: String
 }
 
 impl Item {
@@ -29,12 +32,15 @@ impl Item {
             relic_id: String::new(),
             enchant_id: 0,
             azerite_powers: String::new(),
-            azerite_level: 0
+            azerite_level: 0,
+            equipped: false,
+            origin: 0,
+            variant: String::new()
         }
     }
 
     pub fn from_replaced_item(other: &ReplacedItem) -> Item {
-        Item {
+        let mut item = Item {
             id: other.id,
             slot: Slot::from_str(&other.slot).unwrap(),
             name: other.name.clone(),
@@ -43,7 +49,57 @@ impl Item {
             relic_id: other.relic_id.clone(),
             enchant_id: other.enchant_id,
             azerite_powers: other.azerite_powers.clone(),
-            azerite_level: other.azerite_level
+            azerite_level: other.azerite_level,
+            equipped: true,
+            origin: 0,
+            variant: String::new()
+        };
+
+        item.identify();
+        item
+    }
+
+    /// A short text to identify this item in a report.
+    pub fn description(&self) -> String {
+        if self.name.is_empty() {
+            self.variant().to_string()
+        } else {
+            format!("{} {}{}", self.id, self.name, self.details())
         }
     }
+
+    /// The identity of this item. Copies of an item with other bonus ids,
+    /// gems, enchantment or azerite powers are different variants. It is
+    /// built once by identify.
+    pub fn variant(&self) -> &str {
+        &self.variant
+    }
+
+    /// Build the identity of this item. The item map calls this for every
+    /// item. Whoever changes the details of an item later must call it again.
+    pub fn identify(&mut self) {
+        self.variant = format!("{}{}", self.id, self.details());
+    }
+
+    fn details(&self) -> String {
+        let mut text = String::new();
+
+        if !self.bonus_id.is_empty() {
+            text.push_str(&format!(" bonus_id={}", self.bonus_id));
+        }
+
+        if !self.gem_id.is_empty() {
+            text.push_str(&format!(" gem_id={}", self.gem_id));
+        }
+
+        if self.enchant_id != 0 {
+            text.push_str(&format!(" enchant_id={}", self.enchant_id));
+        }
+
+        if !self.azerite_powers.is_empty() {
+            text.push_str(&format!(" azerite_powers={}", self.azerite_powers));
+        }
+
+        text
+    }
 }
@@ -47,21 +47,45 @@ impl ItemMap {
         }
     }
 
+    /// Add a new item. Every pushed item gets its own origin. All variants
+    /// of it keep this origin. So they are known as the same item.
     pub fn push(&mut self, slot: &Slot, item: &Item) {
+        let mut item = item.clone();
+        item.origin = self.total_items();
+        item.identify();
+
         // search for existiing one
         for iter in &mut self.list {
             if iter.slot == *slot {
-                iter.items.push(item.clone());
+                iter.items.push(item);
                 return;
             }
         }
 
         // insert new one
         let mut entry = pair::Pair::new(slot);
-        entry.items.push(item.clone());
+        entry.items.push(item);
         self.list.push(entry);
     }
 
+    /// Replace every item by the variants returned by f. An item without
+    /// variants is removed. So is a slot without items.
+    pub fn expand<F>(&mut self, f: F) where F: Fn(&Item) -> Vec<Item> {
+        for iter in &mut self.list {
+            let mut items: Vec<Item> = Vec::new();
+            for item in iter.items.iter() {
+                items.extend(f(item).into_iter().map(|mut i| {
+                    i.identify();
+                    i
+                }));
+            }
+
+            iter.items = items;
+        }
+
+        self.list.retain(|p| !p.items.is_empty());
+    }
+
     pub fn get_slot(&self, pattern: ESlot) -> Option<&Vec<Item>> {
         for iter in &self.list {
             if iter.slot.slot == pattern {
@@ -72,6 +96,31 @@ impl ItemMap {
         None
     }
 
+    /// Is there any item with this id?
+    pub fn contains(&self, id: u32) -> bool {
+        self.list.iter().any(|p| p.items.iter().any(|i| i.id == id))
+    }
+
+    /// Is there another variant of this item? Then the item id alone does
+    /// not tell which one is meant.
+    pub fn has_variants(&self, item: &Item) -> bool {
+        let variant = item.variant();
+
+        self.list.iter().any(|p| p.items.iter().any(|i| i.id == item.id && i.variant() != variant))
+    }
+
+    /// All items in their order as text. Every slot is one line.
+    pub fn describe(&self) -> String {
+        let mut text = String::new();
+
+        for iter in self.list.iter() {
+            let items: Vec<&str> = iter.items.iter().map(|i| i.variant()).collect();
+            text.push_str(&format!("{}: {}\n", iter.slot.name, items.join(", ")));
+        }
+
+        text
+    }
+
     pub fn len(&self) -> usize {
         self.list.len()
     }
@@ -4,27 +4,61 @@ pub mod item_map;
 pub mod item;
 pub mod report;
 pub mod statistic;
+pub mod permutation;
+pub mod checkpoint;
+pub mod genetic;
+pub mod upgrade;
+pub mod constraint;
+pub mod runtime;
+pub mod pruning;
+pub mod scheduler;
 
 
 use regex::Regex;
 use configuration::Configuration;
-use std::fs::{File, create_dir_all};
-use std::io::{BufRead, BufReader};
+use std::fs::{File, create_dir_all, read_to_string};
+use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::result::{Result};
-use std::io::{Error};
-use std::process::{Command, Stdio};
+use std::io::{Error, ErrorKind};
+use std::process::{Command, Stdio, ExitStatus};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::mem;
+use std::time::Instant;
 use uuid::*;
 use indicatif::{ProgressBar, ProgressStyle};
 use chrono::{Local, Duration};
-use rand::{thread_rng, Rng};
+use rand::{Rng, SeedableRng, thread_rng};
+use rand::rngs::StdRng;
 
 use item_map::ItemMap;
 use item::Item;
 use slot::{Slot, ESlot};
 use template::Template;
-use configuration::{ReplacedEnchantment};
+use configuration::{ReplacedEnchantment, Azerite};
 use report::Generator;
 use statistic::Statistic;
+use permutation::Permutation;
+use checkpoint::{Checkpoint, Done};
+use genetic::Evolution;
+use upgrade::Upgrade;
+use constraint::Rules;
+use runtime::{Runtime, Calibration};
+use scheduler::{Scheduler, Mode};
+
+
+pub const SEED_FILE: &str = "seed.txt";
+pub const INDEX_FILE: &str = "index.txt";
+
+
+/// Settings that are the same for all combinations of a stage.
+struct Job<'a> {
+    talents: &'a str,
+    options: &'a [String],
+    statistic: Option<&'a Statistic>
+}
 
 
 pub struct Simcraft {
@@ -37,14 +71,23 @@ pub struct Simcraft {
     log_dir: String,
     report: Generator,
     spec: String,
-    talents: String,
+    talents: Vec<String>,
     level: u32,
+    resume: bool,
+    rules: Rules,
+    seed: u64,
+    runtime: Runtime
 }
 
 impl Simcraft {
-    pub fn new(config: &Configuration, talents: &str) -> Simcraft {
+    /// Create a new run. If resume is set, the run continues in this
+    /// directory. Otherwise a new output directory is created.
+    pub fn new(config: &Configuration, talents: &[String], resume: Option<&str>) -> Simcraft {
         // setup directories
-        let output_dir = format!("{}/{}", config.output_dir, Uuid::new_v4().to_string());
+        let output_dir = match resume {
+            Some(dir) => String::from(dir.trim_end_matches('/')),
+            None => format!("{}/{}", config.output_dir, Uuid::new_v4().to_string())
+        };
         let report_dir = format!("{}/{}", output_dir, config.report_dir);
         let compile_dir = format!("{}/compiles", output_dir);
         let log_dir = format!("{}/{}", output_dir, config.log_dir);
@@ -53,6 +96,9 @@ impl Simcraft {
         create_dir_all(&compile_dir).unwrap();
         create_dir_all(&log_dir).unwrap();
 
+        let seed = Simcraft::run_seed(config, &output_dir);
+        println!("Seed: {}", seed);
+
         // add replaced items
         let mut item_map = ItemMap::new();
         for i in &config.replaces.items {
@@ -70,47 +116,217 @@ impl Simcraft {
             log_dir,
             report: Generator::new(config, &report_dir),
             spec: String::new(),
-            talents: String::from(talents),
-            level: 120
+            talents: talents.to_vec(),
+            level: 120,
+            resume: resume.is_some(),
+            rules: Rules::default(),
+            seed,
+            runtime: Runtime::new(::TIME_PER_ITER as f64)
         }
     }
 
-    // returns a tuple.
-    // .0 => absolut value
-    // .1 => approximate value
-    pub fn calculate_iterations(&self) -> (u64, u64) {
-        let iterations = self._calculate_iterations_at(ESlot::Head, 0) as f64;
-        let approximate = ((self.items.len() * self.items.total_items()) as f64 / iterations) * iterations;
+    /// The seed of this run. A seed from the configuration wins over the
+    /// recorded one of a continued run. Without both a random seed is
+    /// used. The seed is stored in the output directory.
+    fn run_seed(config: &Configuration, output_dir: &str) -> u64 {
+        let file = format!("{}/{}", output_dir, SEED_FILE);
+        let recorded = match read_to_string(&file) {
+            Ok(seed) => seed.trim().parse::<u64>().ok(),
+            Err(_) => None
+        };
+
+        let seed = match (config.seed, recorded) {
+            (Some(seed), _) => seed,
+            (None, Some(seed)) => seed,
+            (None, None) => thread_rng().gen::<u64>()
+        };
+
+        Template::store(&file, &seed.to_string()).unwrap();
+        seed
+    }
+
+    /// A new random number generator based on the seed of this run.
+    fn rng(&self) -> StdRng {
+        StdRng::seed_from_u64(self.seed)
+    }
+
+    /// Calculate the number of iterations. Returns a tuple and if the
+    /// second number is an estimate.
+    ///
+    /// .0 => exact number of combinations
+    /// .1 => expected number of simulations. These are all combinations
+    ///       without the ones that the statistic of a resumed run or of a
+    ///       warm start skips. Without such a statistic nothing is known
+    ///       and all combinations are counted. This is only an upper bound.
+    ///       Every later stage simulates the survivors of the stage before
+    ///       again. The genetic search simulates only the configured
+    ///       maximum. A talent sweep simulates them once per talent build.
+    pub fn calculate_iterations(&self) -> ((u64, u64), bool) {
+        let iterations = Permutation::new(self).count();
+        if self.config.search == "upgrade" {
+            let profiles = upgrade::profiles(self).len() as u64;
+            return ((profiles, profiles), true);
+        }
+
+        if let Some(ref genetic) = self.config.genetic {
+            if self.config.search == "genetic" {
+                let expected = genetic.max_sims
+                    .min((genetic.population * genetic.generations) as u64)
+                    .min(iterations);
+                return ((iterations, expected), true);
+            }
+        }
+
+        let checkpoint = if self.resume { Checkpoint::load(&self.output_dir).ok() } else { None };
+        let mut expected = 0u64;
+        let mut estimated = false;
+        for (build, talents) in self.talent_builds().iter().enumerate() {
+            // a resumed run continues the current build at its stage
+            let (stage, done) = match checkpoint {
+                Some(ref checkpoint) if build < checkpoint.build => continue,
+                Some(ref checkpoint) if build == checkpoint.build => (checkpoint.stage, checkpoint.done.len() as u64),
+                _ => (0, 0)
+            };
+
+            let (stages, estimate) = self.build_iterations(build, talents, iterations, checkpoint.as_ref());
+            expected += stages.iter().skip(stage).sum::<u64>().saturating_sub(done);
+            estimated |= estimate;
+        }
 
-        (iterations as u64, approximate as u64)
+        ((iterations, expected), estimated)
     }
 
-    fn _calculate_iterations_at(&self, start_slot: ESlot, skip: usize) -> u64 {
-        let mut slot = start_slot;
-        let mut iterations = match self.items.get_slot(slot) {
-            Some(l) => l.len() - skip,
-            None => 1
+    /// The number of simulations of every stage of a talent build.
+    /// Returns the numbers and if the first stage is estimated by a
+    /// statistic.
+    fn build_iterations(&self,
+        build: usize,
+        talents: &str,
+        iterations: u64,
+        checkpoint: Option<&Checkpoint>) -> (Vec<u64>, bool)
+    {
+        let checkpoint = checkpoint.filter(|c| c.build == build);
+
+        // a later stage knows how many combinations the first one simulated
+        if let Some(checkpoint) = checkpoint {
+            if checkpoint.stage > 0 {
+                return (self.stage_iterations(0, checkpoint.stages[0].len() as u64), false);
+            }
+        }
+
+        match self.expected_iterations(talents, iterations, checkpoint) {
+            Some(count) => (self.stage_iterations(0, count), true),
+            None => (self.stage_iterations(0, iterations), false)
+        }
+    }
+
+    /// The number of simulations of the first stage if the statistic of
+    /// the checkpoint or of an earlier run is used. Combinations with items
+    /// this statistic ignores are not counted. Returns None without such
+    /// a statistic.
+    fn expected_iterations(&self, talents: &str, iterations: u64, checkpoint: Option<&Checkpoint>) -> Option<u64> {
+        let statistic = Statistic::new(&self.config, iterations, self.items.total_items()).ok()?;
+
+        match checkpoint {
+            Some(checkpoint) if !checkpoint.statistic.is_empty() => statistic.restore(&checkpoint.statistic),
+            _ => {
+                let run_dir = self.config.warm_start.as_ref()?.trim_end_matches('/');
+                statistic.set_build(&self.fingerprint(talents).ok()?, talents);
+                statistic.warm_start(run_dir, self.config.statistic.decay).ok()??;
+            }
+        }
+
+        let permutation = Permutation::new(self);
+        Some(permutation.count_without(|item| statistic.ignores(item)))
+    }
+
+    /// The number of simulations of every stage from the given one on.
+    /// count is the number of the given stage. Every later stage simulates
+    /// the survivors of the stage before.
+    fn stage_iterations(&self, stage: usize, count: u64) -> Vec<u64> {
+        let mut counts: Vec<u64> = vec![count];
+        for later in self.config.stages.iter().skip(stage + 1) {
+            let last = counts[counts.len() - 1];
+            counts.push(Simcraft::survivor_count(last, later.keep));
+        }
+
+        counts
+    }
+
+
+    /// Measure the duration of a single simulation on this machine. The
+    /// first combination is simulated with the configured template. If a
+    /// cache is configured, the result is stored per template and simc
+    /// binary. Without a calibration the default duration is used.
+    ///
+    /// Returns the duration of a simulation in seconds.
+    pub fn calibrate(&mut self) -> Result<f64, Error> {
+        let tpl: String = format!("{}/{}", self.config.template_dir, self.config.simcraft.template);
+        let cache = &self.config.simcraft.calibration_cache;
+
+        if !self.config.simcraft.calibrate {
+            return Ok(self.runtime.per_sim());
+        }
+
+        // the first stage decides the duration of a permutation
+        let talents = self.talent_builds()[0].clone();
+        let options: &[String] = match self.config.stages.first() {
+            Some(s) if self.config.search == "permutation" => &s.options,
+            _ => &[]
         };
 
-        // step through all items
-        while let Some(s) = Simcraft::next_slot(slot) {
-            if s == ESlot::Trinket || s == ESlot::Finger || s == ESlot::WeaponHand {
-                iterations *= match self.items.get_slot(s) {
-                    Some(l) => ((l.len() * l.len()) - l.len()) / 2,
-                    None => 1
+        // the compiled template includes the imported ones. So changes of
+        // e.g. the iterations in global_conf_v1.simc are noticed.
+        let fingerprint = Simcraft::hash(&format!("{}\n{}", self.fingerprint(&talents)?, options.join("\n")));
+        if !cache.is_empty() {
+            if let Some(seconds) = Calibration::lookup(cache, &fingerprint, &self.config.simcraft.executeable) {
+                self.runtime = Runtime::new(seconds);
+                return Ok(seconds);
+            }
+        }
+
+        self.template = Template::load(&tpl)?;
+
+        let stack = {
+            let permutation = Permutation::new(self);
+            let first = permutation.iter(&permutation.sequential_order()).next();
+            match first {
+                Some((_, stack)) => stack,
+                None => {
+                    return Err(Error::new(ErrorKind::InvalidInput, "There is no valid combination"));
                 }
-            } else {
-                // normal iterations
-                iterations *= match self.items.get_slot(s) {
-                    Some(l) => l.len(),
-                    None => 1
-                };
             }
+        };
+
+        let job = Job {
+            talents: &talents,
+            options,
+            statistic: None
+        };
 
-            slot = s;
+        // the parse counter 0 is never used by a simulation
+        let item_list = self.build_item_list(&stack).join("\n");
+        self.report_files(0)?;
+        let started = Instant::now();
+        let process_tpl = self.compile_profile(item_list, 0, &job)?;
+        let status = self.run_simc(&process_tpl, 0)?;
+        if !status.success() {
+            return Err(Error::other(format!("simc failed with {} for {}", status, process_tpl)));
         }
+        let seconds = started.elapsed().as_secs_f64();
 
-        iterations as u64
+        if !cache.is_empty() {
+            Calibration::new(&tpl, &fingerprint, &self.config.simcraft.executeable, seconds).store(cache)?;
+        }
+
+        self.runtime = Runtime::new(seconds);
+        Ok(seconds)
+    }
+
+    /// Duration of the given number of simulations in seconds. All jobs
+    /// run at the same time.
+    pub fn estimate(&self, sims: u64) -> u64 {
+        self.runtime.estimate(sims, self.config.simcraft.jobs)
     }
 
 
@@ -118,6 +334,13 @@ impl Simcraft {
         match File::open(file) {
             Ok(file) => {
                 self.parse_simc_file(&file);
+                self.restrict_items()?;
+                self.expand_candidates();
+                self.rules = Rules::new(&self.config, &self.items)?;
+
+                // fail early on a wrong pruning strategy or scheduler
+                pruning::create(&self.config, 0, self.items.total_items())?;
+                Mode::from_str(&self.config.scheduler.mode)?;
                 println!("Number of keys: {}", self.items.len());
                 println!("Found number of Items: {}", self.items.total_items());
                 Ok(true)
@@ -129,80 +352,331 @@ impl Simcraft {
     }
 
 
-    fn build_permutation_array(&self, iteration_count: u64) -> Result<Vec<Vec<Item>>, Error> {
-        let mut iterations: Vec<Vec<Item>> = Vec::new();
-        let mut stack: Vec<Item> = Vec::new();
-        let mut progress_bar: ProgressBar = ProgressBar::new(iteration_count);
+    /// This function start the whole permutation process. The process starts with
+    /// the head slot. This is a single step permutation. Bevor this step, the
+    /// configured template will be load.
+    ///
+    /// If stages are configured, the first stage simulates all combinations.
+    /// Every following stage simulates only the best ones of the stage before.
+    ///
+    /// With several talent builds the whole permutation runs once for every
+    /// build. Each build starts with an empty statistic.
+    pub fn permutation(&mut self, iterations: (u64, u64)) -> Result<(u64), Error> {
+        let now = Local::now();
+        println!("Start permutation with approximatly {} iterations", iterations.1);
+        println!("You can find the outputs at: {}", self.output_dir);
+        println!("Starts at: {}", now.format("%d.%m.%Y - %H:%M:%S"));
+        println!("Finished approximatly at: {}",
+            (now + Duration::seconds(self.estimate(iterations.1) as i64)).format("%d.%m.%Y - %H:%M:%S"));
 
-        // setup progress bar
-        progress_bar.set_style(
-            ProgressStyle::default_bar()
-            .template("{bar:40.cyan/blue} {pos:>7}/{len:7} [{eta_precise}]")
-            .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ "));
+        // generate template
+        let tpl: String = format!("{}/{}", self.config.template_dir, self.config.simcraft.template);
+        self.template = Template::load(&tpl).unwrap();
 
-        // start permutation with head slot
-        self.permut_iteration_single(&mut progress_bar, ESlot::Head, &mut stack, &mut iterations);
-        progress_bar.finish();
-        println!("Iterations generated: {}", iterations.len());
+        // start permutation with random access
+        let builds = self.talent_builds();
+        let statistic = Statistic::new(&self.config, iterations.0, self.items.total_items())?;
+        let simc: &Simcraft = self;
+        let permutation = Permutation::new(simc);
+        let checkpoint = simc.open_checkpoint(&permutation, &statistic)?;
+
+        let order = checkpoint.order.unwrap();
+        let first_build = checkpoint.build;
+        let mut first_stage = checkpoint.stage;
+        let parse_counter = AtomicUsize::new(checkpoint.parse_counter as usize);
+        let checkpoint = Mutex::new(checkpoint);
+        let stage_count = simc.config.stages.len().max(1);
+
+
+        // the expected simulations of every stage of every build. The
+        // estimated end covers all stages and builds that follow.
+        let expected: Vec<Vec<u64>> = {
+            let checkpoint = checkpoint.lock().unwrap();
+            builds.iter().enumerate()
+                .map(|(build, talents)| simc.build_iterations(build, talents, iterations.0, Some(&checkpoint)).0)
+                .collect()
+        };
+
+        println!("Run {} simc processes at once", simc.config.simcraft.jobs.max(1));
+        for (build, talents) in builds.iter().enumerate().skip(first_build) {
+            if builds.len() > 1 {
+                println!("Talents {} ({} of {})", talents, build + 1, builds.len());
+                simc.report.set_talents(talents);
+            }
+
+            // a new talent build can start with the statistic of an earlier run
+            statistic.set_build(&simc.fingerprint(talents)?, talents);
+            if first_stage == 0 && checkpoint.lock().unwrap().done.is_empty() {
+                simc.warm_start(&statistic)?;
+            }
+
+            for stage in first_stage..stage_count {
+                let survivors: Vec<u64> = if stage == 0 {
+                    Vec::new()
+                } else {
+                    Simcraft::select_survivors(&checkpoint.lock().unwrap().stages[stage - 1],
+                        simc.config.stages[stage].keep)
+                };
+
+                let length = if stage == 0 { iterations.0 } else { survivors.len() as u64 };
+                let following: Vec<u64> = if stage == 0 {
+                    expected[build].clone()
+                } else {
+                    simc.stage_iterations(stage, length)
+                };
+                let later = following.iter().skip(1).sum::<u64>()
+                    + expected.iter().skip(build + 1).flatten().sum::<u64>();
+                if stage_count > 1 {
+                    println!("Stage {} of {} with {} combinations", stage + 1, stage_count, length);
+                }
+
+                // setup progress bar
+                let progress_bar: ProgressBar = ProgressBar::new(length);
+                progress_bar.set_style(
+                    ProgressStyle::default_bar()
+                    .template("{bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")
+                    .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ "));
+
+                // a stage without statistic simulates all combinations
+                simc.runtime.start(length, later);
+                progress_bar.set_message(&simc.runtime.message(simc.config.simcraft.jobs));
 
-        // nothing to do
-        Ok(iterations)
+                let options: &[String] = match simc.config.stages.get(stage) {
+                    Some(s) => &s.options,
+                    None => &[]
+                };
+
+                // the statistic is only used by the first stage
+                let job = Job {
+                    talents,
+                    options,
+                    statistic: if stage == 0 { Some(&statistic) } else { None }
+                };
+
+                if stage == 0 {
+                    let finished: HashSet<u64> = checkpoint.lock().unwrap().done.iter().map(|d| d.index).collect();
+                    let source = Scheduler::new(permutation.iter(&order), &statistic, finished, &simc.config.scheduler, simc.rng())?;
+                    simc.run_stage(source, &job, &checkpoint, &parse_counter, &progress_bar)?;
+                } else {
+                    let source = survivors.iter().map(|index| (*index, permutation.get(*index).unwrap()));
+                    simc.run_stage(source, &job, &checkpoint, &parse_counter, &progress_bar)?;
+                }
+
+                progress_bar.finish();
+
+                // keep the results of this stage
+                if stage + 1 < stage_count {
+                    let mut checkpoint = checkpoint.lock().unwrap();
+                    let done = mem::take(&mut checkpoint.done);
+                    checkpoint.stages.push(done);
+                    checkpoint.stage = stage + 1;
+
+                    if builds.len() > 1 {
+                        simc.report.finish_stage(&format!("Stage {} - talents {}", stage + 1, talents));
+                    } else {
+                        simc.report.finish_stage(&format!("Stage {}", stage + 1));
+                    }
+                    checkpoint.reports = simc.report.snapshot();
+                    checkpoint.stage_reports = simc.report.stages();
+                    checkpoint.store(&simc.output_dir)?;
+                }
+            }
+
+            // keep the results of this talent build. The next one starts from scratch.
+            first_stage = 0;
+            if builds.len() > 1 {
+                let mut checkpoint = checkpoint.lock().unwrap();
+                checkpoint.done.clear();
+                checkpoint.stages.clear();
+                checkpoint.stage = 0;
+                checkpoint.build = build + 1;
+
+                statistic.restore(&[]);
+                checkpoint.statistic = Vec::new();
+
+                simc.report.finish_build(talents);
+                checkpoint.reports = simc.report.snapshot();
+                checkpoint.builds = simc.report.builds();
+                checkpoint.store(&simc.output_dir)?;
+            }
+        }
+
+        println!("The strategy {} skipped {} combinations", statistic.strategy(), statistic.skipped());
+
+        // generate report
+        self.report.compile();
+
+        let diff = Local::now() - now;
+        println!("Permutation finished: {}", Local::now().format("%d.%m.%Y - %H:%M:%S"));
+        println!("after: {}", ::fmt_duration(diff.num_seconds() as u64));
+
+        Ok(iterations.0)
     }
 
 
-    /// This function start the whole permutation process. The process starts with
-    /// the head slot. This is a single step permutation. Bevor this step, the
-    /// configured template will be load.
-    pub fn permutation(&mut self, iterations: (u64, u64)) -> Result<(u64), Error> {
+    /// Compile the profiles of all combinations without running simc. The
+    /// profiles are stored in the compile directory like in a real run.
+    /// The index file lists the items of every combination and the
+    /// profile that contains it.
+    ///
+    /// Returns the number of compiled profiles.
+    pub fn dry_run(&mut self, iterations: (u64, u64)) -> Result<u64, Error> {
+        println!("Dry run: compile {} combinations without simc", iterations.0);
+        println!("You can find the profiles at: {}", self.compile_dir);
 
-        println!("Generate permutation array....");
-        let mut permutation = self.build_permutation_array(iterations.0).unwrap();
+        let tpl: String = format!("{}/{}", self.config.template_dir, self.config.simcraft.template);
+        self.template = Template::load(&tpl)?;
+
+        let simc: &Simcraft = self;
+        let builds = simc.talent_builds();
+        let permutation = Permutation::new(simc);
+        let order = permutation.sequential_order();
+        let chunk_size = simc.config.simcraft.profileset_size.max(1);
+
+        // only the first stage simulates all combinations
+        let options: &[String] = match simc.config.stages.first() {
+            Some(s) => &s.options,
+            None => &[]
+        };
 
-        // setup progress bar
-        let progress_bar: ProgressBar = ProgressBar::new(iterations.0 + 2);
+        let index_file = format!("{}/{}", simc.compile_dir, INDEX_FILE);
+        let mut index = BufWriter::new(File::create(&index_file)?);
+        writeln!(index, "file\tcombination\tprofileset\ttalents\titems")?;
+
+        let progress_bar: ProgressBar = ProgressBar::new(iterations.0 * builds.len() as u64);
         progress_bar.set_style(
             ProgressStyle::default_bar()
             .template("{bar:40.cyan/blue} {pos:>7}/{len:7} [{eta_precise}]")
             .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ "));
 
+        let mut parse_counter = 0u64;
+        for talents in builds.iter() {
+            let job = Job { talents, options, statistic: None };
+            let mut combinations = permutation.iter(&order);
+
+            loop {
+                let (indices, chunk): (Vec<u64>, Vec<Vec<Item>>) = combinations.by_ref().take(chunk_size).unzip();
+                if chunk.is_empty() {
+                    break;
+                }
+
+                parse_counter += 1;
+                let (item_list, names) = if chunk_size > 1 {
+                    simc.profileset_item_list(&chunk)
+                } else {
+                    (simc.build_item_list(&chunk[0]).join("\n"), vec![String::new()])
+                };
+
+                simc.compile_profile(item_list, parse_counter, &job)?;
+
+                let file = simc.process_file(parse_counter);
+                for ((combination, stack), name) in indices.iter().zip(chunk.iter()).zip(names.iter()) {
+                    writeln!(index, "{}\t{}\t{}\t{}\t{}",
+                        file,
+                        combination,
+                        if name.is_empty() { "-" } else { name },
+                        if talents.is_empty() { "-" } else { talents },
+                        simc.build_item_list(stack).join(" "))?;
+                }
+
+                progress_bar.inc(chunk.len() as u64);
+            }
+        }
+
+        index.flush()?;
+        progress_bar.finish();
+
+        println!("Compiled {} profiles", parse_counter);
+        println!("Index of all combinations: {}", index_file);
+
+        Ok(parse_counter)
+    }
+
+
+    /// Search the best combinations with a genetic algorithm. This is used if
+    /// there are too many combinations to simulate all of them. The search
+    /// stops after the configured number of generations or simulations.
+    pub fn genetic(&mut self, iterations: (u64, u64)) -> Result<u64, Error> {
+        let genetic = match self.config.genetic {
+            Some(ref genetic) => genetic.clone(),
+            None => {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                    "Genetic search needs a genetic section in the configuration"));
+            }
+        };
+
         let now = Local::now();
-        println!("Start permutation with approximatly {} iterations", iterations.1);
+        println!("Start genetic search with at most {} simulations", iterations.1);
         println!("You can find the outputs at: {}", self.output_dir);
         println!("Starts at: {}", now.format("%d.%m.%Y - %H:%M:%S"));
-        println!("Finished approximatly at: {}",
-            (now + Duration::seconds((iterations.1 * ::TIME_PER_ITER) as i64)).format("%d.%m.%Y - %H:%M:%S"));
 
         // generate template
         let tpl: String = format!("{}/{}", self.config.template_dir, self.config.simcraft.template);
         self.template = Template::load(&tpl).unwrap();
 
-        progress_bar.inc(1);
+        let statistic = Statistic::new(&self.config, iterations.0, self.items.total_items())?;
+        let simc: &Simcraft = self;
+        let permutation = Permutation::new(simc);
+        if iterations.0 == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "There is no valid combination"));
+        }
 
-        // start permutation with random access
-        let mut rng = thread_rng();
-        let mut parse_counter = 0u64;
-        let statistic = Statistic::new(&self.config, iterations.0, self.items.total_items());
+        let checkpoint = simc.open_checkpoint(&permutation, &statistic)?;
+        let evolution = Evolution::new(&permutation, &genetic);
+        let talents = simc.single_talent_build();
+        let job = Job {
+            talents: &talents,
+            options: &[],
+            statistic: None
+        };
+        let mut rng = simc.rng();
 
-        while permutation.len() > 0 {
-            let index = rng.gen_range(0, permutation.len());
-            
-            // if no ignores in these set, then we can process the simc file
-            if !statistic.has_ignores(&permutation[index]) {
-                // calculate dps and so on
-                // tuple(parse_count, dps, min_dps, max_dps)
-                let tuple = self.process_simc_file(&permutation[index], parse_counter);
-                parse_counter = tuple.0;
+        // dps of all simulated combinations. A continued run knows some of them.
+        let mut fitness: HashMap<u64, f32> = checkpoint.done.iter().map(|d| (d.index, d.dps)).collect();
+        let parse_counter = AtomicUsize::new(checkpoint.parse_counter as usize);
+        let checkpoint = Mutex::new(checkpoint);
 
-                // update statistical data
-                statistic.update(&permutation[index], tuple.1, tuple.2, tuple.3);
+        // setup progress bar
+        let progress_bar: ProgressBar = ProgressBar::new(iterations.1);
+        progress_bar.set_style(
+            ProgressStyle::default_bar()
+            .template("{bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")
+            .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ "));
+        progress_bar.inc(fitness.len() as u64);
+
+        simc.runtime.start(iterations.1.saturating_sub(fitness.len() as u64), 0);
+        progress_bar.set_message(&simc.runtime.message(simc.config.simcraft.jobs));
+
+        let mut population = evolution.populate(&mut rng);
+        if population.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                "The genetic search found no valid combination for the first population"));
+        }
+
+        for _ in 0..genetic.generations {
+            // simulate all new individuals, but not more than allowed
+            let mut unknown: Vec<u64> = Vec::new();
+            for index in population.iter() {
+                if !fitness.contains_key(index) && !unknown.contains(index) {
+                    unknown.push(*index);
+                }
             }
 
-            // remove stack from array
-            permutation.remove(index);
+            unknown.truncate(genetic.max_sims.saturating_sub(fitness.len() as u64) as usize);
 
-            // handle progress bar
-            progress_bar.inc(1);
-        };
+            let source = unknown.iter().map(|index| (*index, permutation.get(*index).unwrap()));
+            simc.run_stage(source, &job, &checkpoint, &parse_counter, &progress_bar)?;
+
+            for done in checkpoint.lock().unwrap().done.iter() {
+                fitness.insert(done.index, done.dps);
+            }
+
+            if fitness.len() as u64 >= genetic.max_sims {
+                break;
+            }
+
+            population = evolution.evolve(&mut rng, &population, &fitness);
+        }
 
         progress_bar.finish();
 
@@ -210,142 +684,436 @@ impl Simcraft {
         self.report.compile();
 
         let diff = Local::now() - now;
-        println!("Permutation finished: {}", Local::now().format("%d.%m.%Y - %H:%M:%S"));
+        println!("Genetic search finished: {}", Local::now().format("%d.%m.%Y - %H:%M:%S"));
         println!("after: {}", ::fmt_duration(diff.num_seconds() as u64));
 
-        Ok(iterations.0)
+        Ok(fitness.len() as u64)
     }
 
 
-    /// This method process a single iteration of the permutation. This means it will
-    /// step through all items of a single slot. For every item of this slot another
-    /// permutation method will be called.
-    fn permut_iteration_single(
-        &self,
-        progress_bar: &mut ProgressBar,
-        slot: ESlot,
-        stack: &mut Vec<Item>,
-        iterations: &mut Vec<Vec<Item>>
-    ) {
-        // off_hand could be empty. So we need to jump over
-        let items = match self.items.get_slot(slot) {
-            Some(i) => i,
+    /// Search for upgrades of the equipped items. The equipped items are
+    /// simulated once as baseline. Then every item of the bag is swapped
+    /// into its slot and simulated. The report shows the difference of
+    /// each item to the baseline.
+    pub fn upgrade(&mut self) -> Result<u64, Error> {
+        let now = Local::now();
+        println!("Start upgrade search");
+        println!("You can find the outputs at: {}", self.output_dir);
+        println!("Starts at: {}", now.format("%d.%m.%Y - %H:%M:%S"));
+
+        // generate template
+        let tpl: String = format!("{}/{}", self.config.template_dir, self.config.simcraft.template);
+        self.template = Template::load(&tpl).unwrap();
+
+        let statistic = Statistic::new(&self.config, 0, self.items.total_items())?;
+        let simc: &Simcraft = self;
+        let permutation = Permutation::new(simc);
+        let profiles = upgrade::profiles(simc);
+        let checkpoint = simc.open_checkpoint(&permutation, &statistic)?;
+        let parse_counter = AtomicUsize::new(checkpoint.parse_counter as usize);
+        let checkpoint = Mutex::new(checkpoint);
+
+        // setup progress bar
+        let progress_bar: ProgressBar = ProgressBar::new(profiles.len() as u64);
+        progress_bar.set_style(
+            ProgressStyle::default_bar()
+            .template("{bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")
+            .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ "));
+
+        simc.runtime.start(profiles.len() as u64, 0);
+        progress_bar.set_message(&simc.runtime.message(simc.config.simcraft.jobs));
+
+        let talents = simc.single_talent_build();
+        let job = Job {
+            talents: &talents,
+            options: &[],
+            statistic: None
+        };
+
+        let source = profiles.iter().enumerate().map(|(n, p)| (n as u64, p.stack.clone()));
+        simc.run_stage(source, &job, &checkpoint, &parse_counter, &progress_bar)?;
+        progress_bar.finish();
+
+        // compare all candidates with the baseline
+        let done: HashMap<u64, Done> = checkpoint.lock().unwrap().done.iter()
+            .map(|d| (d.index, d.clone()))
+            .collect();
+        let baseline = match done.get(&0) {
+            Some(d) => d.clone(),
             None => {
-                return self.handle_iteration_step(progress_bar, slot, stack, iterations);
+                return Err(Error::other("Baseline was not simulated"));
             }
         };
 
-        for item in items.iter() {
-            // check limits
-            if self.has_multiple_of_them(stack) {
-                continue;
+        let mut upgrades: Vec<Upgrade> = Vec::new();
+        for (n, profile) in profiles.iter().enumerate().skip(1) {
+            if let (Some(candidate), Some(result)) = (&profile.candidate, done.get(&(n as u64))) {
+                upgrades.push(Upgrade {
+                    group: Slot::from_enum(Slot::fix_slot(profile.slot.slot)).name,
+                    slot: profile.slot.get_name(),
+                    item: candidate.description(),
+                    html: result.report.clone(),
+                    dps: result.dps,
+                    delta: result.dps - baseline.dps,
+                    percent: (result.dps - baseline.dps) / baseline.dps * 100.0
+                });
             }
+        }
+
+        // generate report
+        self.report.compile_upgrades(baseline.dps, &baseline.report, &upgrades);
 
-            // add new one to the stack
-            stack.push(item.clone());
+        let diff = Local::now() - now;
+        println!("Upgrade search finished: {}", Local::now().format("%d.%m.%Y - %H:%M:%S"));
+        println!("after: {}", ::fmt_duration(diff.num_seconds() as u64));
 
-            // handle iteration step
-            self.handle_iteration_step(progress_bar, slot, stack, iterations);
+        Ok(profiles.len() as u64)
+    }
 
-            // remove that from stack
-            stack.pop();
+
+    /// Continue an interrupted run or start a new one. A new run gets
+    /// a random order of all combinations.
+    fn open_checkpoint(&self, permutation: &Permutation, statistic: &Statistic) -> Result<Checkpoint, Error> {
+        if self.resume {
+            let checkpoint = Checkpoint::load(&self.output_dir)?;
+            let fingerprint = checkpoint.fingerprint == self.space_fingerprint();
+            match checkpoint.order {
+                Some(order) if order.size == permutation.count() && fingerprint => (),
+                _ => {
+                    return Err(Error::new(ErrorKind::InvalidData,
+                        "Checkpoint does not match the input. Items or limits have changed."));
+                }
+            }
+
+            println!("Resume run at stage {} with {} finished simulations",
+                checkpoint.stage + 1, checkpoint.done.len());
+            statistic.restore(&checkpoint.statistic);
+            self.report.restore(&checkpoint.reports);
+            self.report.restore_stages(&checkpoint.stage_reports);
+            self.report.restore_builds(&checkpoint.builds);
+            Ok(checkpoint)
+        } else {
+            let checkpoint = Checkpoint::new(&permutation.random_order(&mut self.rng()), &self.space_fingerprint());
+            checkpoint.store(&self.output_dir)?;
+            Ok(checkpoint)
         }
     }
 
 
-    /// This method does generaly the same as Simcraft::permut_iteration_single. Except 
-    /// that this method steps through all items of a single slot twice. This is needed
-    /// because that rings and trinkets has two slots.
-    fn permut_iteration_double(
-        &self,
-        progress_bar: &mut ProgressBar,
-        slot: ESlot,
-        stack: &mut Vec<Item>,
-        iterations: &mut Vec<Vec<Item>>
-    ) {
-        let mut counter = 0;
+    /// Simulate all combinations of the source. Runs as many jobs as
+    /// configured. Combinations that are already done by an interrupted
+    /// run are skipped. If the job has a statistic, it is used to skip
+    /// combinations with bad rated items.
+    fn run_stage<I>(&self,
+        source: I,
+        job: &Job,
+        checkpoint: &Mutex<Checkpoint>,
+        parse_counter: &AtomicUsize,
+        progress_bar: &ProgressBar) -> Result<(), Error>
+        where I: Iterator<Item = (u64, Vec<Item>)> + Send
+    {
+        let finished: HashSet<u64> = checkpoint.lock().unwrap().done.iter().map(|d| d.index).collect();
+        let statistic = job.statistic;
+        let queue = Mutex::new(source);
+        let stored = Mutex::new(Instant::now());
+
+        // every job takes the next combinations from the queue. With profilesets
+        // a job takes a whole chunk of them.
+        let chunk_size = self.config.simcraft.profileset_size.max(1);
+        let results: Vec<Result<(), Error>> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.config.simcraft.jobs.max(1)).map(|_| scope.spawn(|| {
+                loop {
+                    let mut indices: Vec<u64> = Vec::new();
+                    let mut chunk: Vec<Vec<Item>> = Vec::new();
+                    {
+                        let mut queue = queue.lock().unwrap();
+                        while chunk.len() < chunk_size {
+                            let (index, stack) = match queue.next() {
+                                Some(next) => next,
+                                None => break
+                            };
+
+                            // already done by an interrupted run or no ignores
+                            // in these set, then we can process the simc file
+                            let done = finished.contains(&index);
+                            let ignore = match statistic {
+                                Some(statistic) if !done && statistic.has_ignores(&stack) => {
+                                    statistic.skip();
+                                    true
+                                },
+                                _ => false
+                            };
+
+                            if done || ignore {
+                                self.runtime.skip(ignore);
+                                progress_bar.inc(1);
+                            } else {
+                                indices.push(index);
+                                chunk.push(stack);
+                            }
+                        }
+                    }
 
-        // slot finger1
-        let slot1_items = match self.items.get_slot(slot) {
-            Some(i) => i,
-            None => {
-                return self.handle_iteration_step(progress_bar, slot, stack, iterations);
-            }
-        };
+                    if chunk.is_empty() {
+                        break;
+                    }
 
-        for slot1 in slot1_items.iter() {
-            // check limits
-            if self.has_multiple_of_them(&stack) {
-                continue;
-            }
+                    let counter = parse_counter.fetch_add(1, Ordering::SeqCst) + 1;
+                    let started = Instant::now();
+                    let (report_json, report_html, names) = if chunk_size > 1 {
+                        self.process_profileset(&chunk, counter as u64, job)
+                    } else {
+                        let (report_json, report_html) = self.process_simc_file(&chunk[0], counter as u64, job);
+                        (report_json, report_html, Vec::new())
+                    };
+
+                    // the measured duration updates the estimated end
+                    self.runtime.record(chunk.len() as u64, started.elapsed());
+                    progress_bar.set_message(&self.runtime.message(self.config.simcraft.jobs));
+
+                    // the reports are added under the lock of the checkpoint.
+                    // So a stored checkpoint has a report for every done
+                    // combination and no others.
+                    // tuple(dps, min_dps, max_dps, error)
+                    let mut checkpoint = checkpoint.lock().unwrap();
+                    let results = self.push_reports(&chunk, &report_json, &report_html, &names);
+                    for ((index, stack), tuple) in indices.iter().zip(chunk.iter()).zip(results.iter()) {
+                        // update statistical data
+                        if let Some(statistic) = statistic {
+                            statistic.update(stack, tuple.0, tuple.3, tuple.1, tuple.2);
+                        }
 
-            // add my own one
-            let mut item = slot1.clone();
-            item.slot = Slot::get_real_slot(&slot1.slot, 1).unwrap();
-            stack.push(item);
+                        checkpoint.done.push(Done {
+                            index: *index,
+                            dps: tuple.0,
+                            report: self.report_file(&self.config.simcraft.html, counter as u64)
+                        });
 
-            // slot finger2
-            let slot2_items = self.items.get_slot(slot).unwrap();
-            for slot2 in slot2_items.iter().skip(counter) {
-                // cannot add the same item on both slots
-                if slot1.id == slot2.id {
-                    continue;
-                }
+                        // handle progress bar
+                        progress_bar.inc(1);
+                    }
 
-                // check limits
-                if self.has_multiple_of_them(&stack) {
-                    continue;
+                    // save the current state. The list of done combinations
+                    // grows with every simulation. So it is written only
+                    // once per interval.
+                    let mut stored = stored.lock().unwrap();
+                    if stored.elapsed().as_secs() >= self.config.simcraft.checkpoint_interval {
+                        self.save_checkpoint(&mut checkpoint, parse_counter, statistic)?;
+                        *stored = Instant::now();
+                    }
                 }
 
-                // add my own one
-                let mut item = slot2.clone();
-                item.slot = Slot::get_real_slot(&slot2.slot, 2).unwrap();
-                stack.push(item);
+                Ok(())
+            })).collect();
 
-                // step into next iteration
-                self.handle_iteration_step(progress_bar, slot, stack, iterations);
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
 
-                stack.pop(); // remove item from stack
-            }
+        for result in results {
+            result?;
+        }
+
+        self.save_checkpoint(&mut checkpoint.lock().unwrap(), parse_counter, statistic)
+    }
+
+    /// Write the current state of a stage into the checkpoint. Only the
+    /// first stage collects the statistic. It is exported with the
+    /// checkpoint. So an interrupted run can be used for a warm start, too.
+    fn save_checkpoint(&self,
+        checkpoint: &mut Checkpoint,
+        parse_counter: &AtomicUsize,
+        statistic: Option<&Statistic>) -> Result<(), Error>
+    {
+        checkpoint.parse_counter = parse_counter.load(Ordering::SeqCst) as u64;
+        if let Some(statistic) = statistic {
+            checkpoint.statistic = statistic.snapshot();
+            statistic.store(&self.output_dir)?;
+        }
+        checkpoint.reports = self.report.snapshot();
+        checkpoint.store(&self.output_dir)
+    }
+
+
+    /// Import the statistic of the run given by warm_start. It is skipped
+    /// if the template or the talents are different.
+    fn warm_start(&self, statistic: &Statistic) -> Result<(), Error> {
+        let run_dir = match self.config.warm_start {
+            Some(ref run_dir) => run_dir.trim_end_matches('/'),
+            None => return Ok(())
+        };
 
-            stack.pop(); // remove item from stack
-            counter += 1; // increase the counter to skip items
+        match statistic.warm_start(run_dir, self.config.statistic.decay)? {
+            Some(count) => println!("Warm start with {} items from {}", count, run_dir),
+            None => println!("No warm start from {}: the template or the talents are different", run_dir)
         }
+
+        Ok(())
     }
 
+    /// Fingerprint of the template and the talents. The statistic of
+    /// another run can only be used if both are the same. The template
+    /// is compiled. So changes of imported templates, like the fight
+    /// style, are part of it.
+    fn fingerprint(&self, talents: &str) -> Result<String, Error> {
+        let tpl: String = format!("{}/{}", self.config.template_dir, self.config.simcraft.template);
+        let content = Template::load(&tpl)?.compile()?;
 
-    fn handle_iteration_step(
-        &self,
-        progress_bar: &mut ProgressBar,
-        slot: ESlot,
-        stack: &mut Vec<Item>,
-        iterations: &mut Vec<Vec<Item>>
-    ) {
-        if let Some(next) = Simcraft::next_slot(slot) {
-            match next {
-                ESlot::Finger => self.permut_iteration_double(progress_bar, next, stack, iterations),
-                ESlot::Trinket => self.permut_iteration_double(progress_bar, next, stack, iterations),
-                ESlot::WeaponHand => self.permut_iteration_double(progress_bar, next, stack, iterations),
-                _ => self.permut_iteration_single(progress_bar, next, stack, iterations)
-            };
+        Ok(Simcraft::hash(&format!("{}\n{}", content, talents)))
+    }
+
+    /// Fingerprint of all combinations. These are the items in their order
+    /// and all rules. The index of a combination of a checkpoint is only
+    /// valid if both are the same.
+    fn space_fingerprint(&self) -> String {
+        let rules = (&self.config.limits, &self.config.unique, &self.config.sets,
+            &self.config.constraints, &self.config.weapons);
 
-            return;
+        Simcraft::hash(&format!("{}{}", self.items.describe(), serde_json::to_string(&rules).unwrap()))
+    }
+
+    /// This uses FNV-1a because it does not change between versions of Rust.
+    fn hash(content: &str) -> String {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in content.bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+
+        format!("{:016x}", hash)
+    }
+
+    /// All talent builds of this run. Without any talents the ones of
+    /// the template are used.
+    fn talent_builds(&self) -> Vec<String> {
+        if self.talents.is_empty() {
+            vec![String::new()]
+        } else {
+            self.talents.clone()
+        }
+    }
+
+    /// The genetic and the upgrade search support only one talent build.
+    fn single_talent_build(&self) -> String {
+        let builds = self.talent_builds();
+        if builds.len() > 1 {
+            println!("Only the first talent build is used: {}", builds[0]);
         }
 
-        progress_bar.inc(1);
-        iterations.push(stack.clone());
+        builds[0].clone()
     }
 
 
+    /// Select the best combinations of a stage. keep is the fraction of
+    /// combinations that are used by the next stage.
+    fn select_survivors(done: &[Done], keep: f32) -> Vec<u64> {
+        let mut sorted: Vec<Done> = done.to_vec();
+        sorted.sort_by(|a, b| b.dps.partial_cmp(&a.dps).unwrap());
+
+        let count = Simcraft::survivor_count(sorted.len() as u64, keep);
+        sorted.iter().take(count as usize).map(|d| d.index).collect()
+    }
+
+    /// The number of survivors of a stage with count combinations. At
+    /// least one combination survives.
+    fn survivor_count(count: u64, keep: f32) -> u64 {
+        ((count as f32 * keep).ceil() as u64).max(1).min(count)
+    }
+
+
+    /// Simulate a single combination. The parse_counter must be unique. It
+    /// is used to name the compiled template, the reports and the logs. So
+    /// this can be called by many jobs at once.
+    ///
+    /// Returns the json and the html report.
     fn process_simc_file(&self, 
-        stack: &Vec<Item>,
-        parse_counter: u64) -> (u64, f32, f32, f32)
+        stack: &[Item],
+        parse_counter: u64,
+        job: &Job) -> (String, String)
+    {
+        let item_list = self.build_item_list(stack).join("\n");
+        self.execute_simc(item_list, parse_counter, job)
+    }
+
+    /// Simulate a chunk of combinations with one simc process. The first
+    /// combination is the base actor. All others are added as profileset.
+    /// simc only simulates the differences to the base actor.
+    ///
+    /// Returns the json and the html report and the names of all profilesets.
+    fn process_profileset(&self,
+        chunk: &[Vec<Item>],
+        parse_counter: u64,
+        job: &Job) -> (String, String, Vec<String>)
     {
-        // setup template
-        let parse_counter = parse_counter + 1;
+        let (item_list, names) = self.profileset_item_list(chunk);
+        let (report_json, report_html) = self.execute_simc(item_list, parse_counter, job);
+
+        (report_json, report_html, names)
+    }
+
+    /// Add the reports of a simulated chunk. Without names the chunk is a
+    /// single combination.
+    ///
+    /// Returns a tuple with (dps, min_dps, max_dps, error) for every combination.
+    fn push_reports(&self,
+        chunk: &[Vec<Item>],
+        report_json: &str,
+        report_html: &str,
+        names: &[String]) -> Vec<(f32, f32, f32, f32)>
+    {
+        if names.is_empty() {
+            let tuple = self.report.push(report_json, report_html, &self.report_info(&chunk[0]));
+            return vec![(tuple.1, tuple.2, tuple.3, tuple.4)];
+        }
+
+        // the html report shows only the base actor. So every profileset
+        // lists its own items.
+        let info: Vec<String> = chunk.iter().enumerate().map(|(n, stack)| {
+            if n == 0 {
+                self.report_info(stack)
+            } else {
+                self.profileset_info(stack)
+            }
+        }).collect();
+
+        self.report.push_profilesets(report_json, report_html, names, &info)
+    }
+
+    /// Build the item list of a chunk. The first combination is the base
+    /// actor, all others are profilesets.
+    ///
+    /// Returns the item list and the names of all profilesets. The base
+    /// actor has an empty name.
+    fn profileset_item_list(&self, chunk: &[Vec<Item>]) -> (String, Vec<String>) {
+        let mut names: Vec<String> = Vec::new();
+        let mut item_list = self.build_item_list(&chunk[0]).join("\n");
+        item_list.push('\n');
+        names.push(String::new());
+
+        for (n, stack) in chunk.iter().enumerate().skip(1) {
+            let name = format!("combination_{}", n);
+
+            item_list.push('\n');
+            for entry in self.build_item_list(stack) {
+                item_list.push_str(&format!("profileset.\"{}\"+={}\n", name, entry));
+            }
+
+            // a profileset only overrides the listed items. Without an off
+            // hand, e.g. with a two-handed weapon, the one of the base
+            // actor has to be removed.
+            if !stack.iter().any(|i| i.slot.get_name() == "off_hand") {
+                item_list.push_str(&format!("profileset.\"{}\"+=off_hand=\n", name));
+            }
+
+            names.push(name);
+        }
+
+        (item_list, names)
+    }
+
+    /// Build a simc item declaration for every item of the stack.
+    fn build_item_list(&self, stack: &[Item]) -> Vec<String> {
+        let mut item_list: Vec<String> = Vec::new();
 
-        // build the item list
-        let mut item_list: String = String::new();
         for item in stack.iter() {
             let mut entry: String = format!("{}=,id={}", &item.slot.get_name(), item.id);
 
@@ -361,8 +1129,10 @@ impl Simcraft {
                 entry.push_str(&format!(",bonus_id={}", item.bonus_id));
             }
 
-            // has replaced enchantment
-            if let Some(enchant) = self.get_replaced_enchantment(&item.slot) {
+            // has replaced enchantment. Candidates are already set by the variant.
+            let replaced = self.get_replaced_enchantment(&item.slot)
+                .filter(|e| e.candidates.is_empty() && e.id != 0);
+            if let Some(enchant) = replaced {
                 entry.push_str(&format!(",enchant_id={}", enchant.id));
             } else if item.enchant_id != 0 {
                 entry.push_str(&format!(",enchant_id={}", item.enchant_id));
@@ -376,62 +1146,112 @@ impl Simcraft {
                 entry.push_str(&format!(",azerite_powers={}", item.azerite_powers));
             }
 
-            item_list.push_str(&entry);
-            item_list.push('\n');
+            item_list.push(entry);
         }
 
-        // setup reports
-        let report_html = format!("{}/{}", self.report_dir, self.config.simcraft.html.replace("{}", &parse_counter.to_string()));
-        let report_json = format!("{}/{}", self.report_dir, self.config.simcraft.json.replace("{}", &parse_counter.to_string()));
+        item_list
+    }
+
+    /// Compile the template with the given item list and run simc.
+    ///
+    /// Returns a tuple with (report_json, report_html)
+    fn execute_simc(&self, item_list: String, parse_counter: u64, job: &Job) -> (String, String) {
+        let (report_json, report_html) = self.report_files(parse_counter).unwrap();
+        let process_tpl = self.compile_profile(item_list, parse_counter, job).unwrap();
+        self.run_simc(&process_tpl, parse_counter).unwrap();
+
+        (report_json, report_html)
+    }
+
+    /// The json and html report of a simulation. Their directories are
+    /// created, simc does not do it.
+    ///
+    /// Returns a tuple with (json, html).
+    fn report_files(&self, parse_counter: u64) -> Result<(String, String), Error> {
+        let report_html = self.report_file(&self.config.simcraft.html, parse_counter);
+        let report_json = self.report_file(&self.config.simcraft.json, parse_counter);
+
+        create_dir_all(Simcraft::extract_path(&report_html))?;
+        create_dir_all(Simcraft::extract_path(&report_json))?;
+
+        Ok((report_json, report_html))
+    }
+
+    /// Run simc with a compiled template. The output is written to the
+    /// log directory.
+    fn run_simc(&self, process_tpl: &str, parse_counter: u64) -> Result<ExitStatus, Error> {
+        let stdout = format!("{}/{}_{}.log", &self.log_dir, "stdout", &parse_counter.to_string());
+        let stdout = File::create(&stdout)?;
 
-        create_dir_all(Simcraft::extract_path(&report_html)).unwrap();
-        create_dir_all(Simcraft::extract_path(&report_json)).unwrap();
+        let stderr = format!("{}/{}_{}.log", &self.log_dir, "stderr", &parse_counter.to_string());
+        let stderr = File::create(&stderr)?;
 
-        self.template.set_var("report_html", &report_html).unwrap();
-        self.template.set_var("report_json", &report_json).unwrap();
+        let mut process = Command::new(&self.config.simcraft.executeable)
+            .arg(process_tpl)
+            .stdout(Stdio::from(stdout))
+            .stderr(Stdio::from(stderr))
+            .spawn()?;
+
+        process.wait()
+    }
+
+    /// Compile the template with the given item list and store it in the
+    /// compile directory. All options of the job are added at the end of
+    /// the compiled template. So they override the settings of the template.
+    ///
+    /// Returns the path of the compiled template.
+    fn compile_profile(&self, item_list: String, parse_counter: u64, job: &Job) -> Result<String, Error> {
+        let mut vars: HashMap<String, String> = HashMap::new();
+
+        // setup reports
+        vars.insert(String::from("report_html"), self.report_file(&self.config.simcraft.html, parse_counter));
+        vars.insert(String::from("report_json"), self.report_file(&self.config.simcraft.json, parse_counter));
 
         // setup list of all items
-        self.template.set_var("item_list", &item_list).unwrap();
+        vars.insert(String::from("item_list"), item_list);
 
         if self.template.var_exist("spec") && self.config.simcraft.override_spec {
-            self.template.set_var("spec", &self.spec).unwrap();
+            vars.insert(String::from("spec"), self.spec.clone());
         }
 
-        if self.template.var_exist("talents") && self.config.simcraft.override_talents {
-            self.template.set_var("talents", &self.talents).unwrap();
+        // a talent sweep needs always its own talents
+        if self.template.var_exist("talents") &&
+        (self.config.simcraft.override_talents || self.talents.len() > 1) {
+            vars.insert(String::from("talents"), String::from(job.talents));
         }
 
         if self.template.var_exist("level") && self.config.simcraft.override_spec {
-            self.template.set_var("level", &self.level.to_string()).unwrap();
+            vars.insert(String::from("level"), self.level.to_string());
+        }
+
+        // use the seed of this run. Without a variable it is added at the end.
+        let append_seed = self.config.simcraft.override_seed && !self.template.var_exist("seed");
+        if self.template.var_exist("seed") && self.config.simcraft.override_seed {
+            vars.insert(String::from("seed"), self.seed.to_string());
         }
 
         // compile template
-        let process_tpl = format!("{}/{}", &self.compile_dir,
-            self.config.simcraft.process_template.replace("{}", &parse_counter.to_string()));
+        let process_tpl = format!("{}/{}", &self.compile_dir, self.process_file(parse_counter));
 
         //println!("Run {} with compiled template {}", &self.config.simcraft.executeable, &process_tpl);
 
-        Template::store(&process_tpl, &self.template.compile().unwrap()).unwrap();
-
-        // execute template
-        let stdout = format!("{}/{}_{}.log", &self.log_dir, "stdout", &parse_counter.to_string());
-        let stdout = File::create(&stdout).unwrap();
+        let mut compiled = self.template.compile_with(&vars)?;
+        if append_seed {
+            compiled.push_str(&format!("\nseed={}", self.seed));
+        }
 
-        let stderr = format!("{}/{}_{}.log", &self.log_dir, "stderr", &parse_counter.to_string());
-        let stderr = File::create(&stderr).unwrap();
+        for option in job.options.iter() {
+            compiled.push_str(&format!("\n{}", option));
+        }
 
-        let mut process = Command::new(&self.config.simcraft.executeable)
-            .arg(process_tpl)
-            .stdout(Stdio::from(stdout))
-            .stderr(Stdio::from(stderr))
-            .spawn().unwrap();
-        process.wait().unwrap();
+        Template::store(&process_tpl, &compiled)?;
 
-        // generate report
-        let tuple = self.report.push(&report_json, &report_html);
-        //self.statistic.update(stack, tuple.1, tuple.2, tuple.3);
+        Ok(process_tpl)
+    }
 
-        (parse_counter, tuple.1, tuple.2, tuple.3)
+    /// File name of a compiled template.
+    fn process_file(&self, parse_counter: u64) -> String {
+        self.config.simcraft.process_template.replace("{}", &parse_counter.to_string())
     }
 
     /// Search for item declarations
@@ -470,7 +1290,7 @@ impl Simcraft {
                     if self.talents.is_empty() {
                         let regex_talents = Regex::new("^talents=(.*)$").unwrap();
                         if let Some(talents) = regex_talents.captures(&line) {
-                            self.talents = String::from(&talents[1]);
+                            self.talents.push(String::from(&talents[1]));
                         }
                     }
 
@@ -517,6 +1337,9 @@ impl Simcraft {
 
                         // save slot
                         item.slot = slot.clone();
+
+                        // items in the bag are commented out
+                        item.equipped = !line.starts_with('#');
                         
                         // extract id's
                         for cap_ids in regex_ids.captures_iter(&cap_item[3]) {
@@ -543,6 +1366,10 @@ impl Simcraft {
     }
 
 
+    fn report_file(&self, pattern: &str, parse_counter: u64) -> String {
+        format!("{}/{}", self.report_dir, pattern.replace("{}", &parse_counter.to_string()))
+    }
+
     fn extract_path(path: &str) -> String {
         let p = match String::from(path).rfind('/') {
             Some(n) => n,
@@ -574,6 +1401,159 @@ impl Simcraft {
         None
     }
 
+    /// Narrow the items of the run. Locked slots and all slots that are not
+    /// in only keep their equipped items. Excluded items are removed.
+    /// Included items are kept in every case.
+    fn restrict_items(&mut self) -> Result<(), Error> {
+        let restrict = self.config.restrict.clone();
+        let lock = self.slots_of(&restrict.lock)?;
+        let only = self.slots_of(&restrict.only)?;
+
+        let mut items = mem::replace(&mut self.items, ItemMap::new());
+        items.expand(|item| {
+            let fixed = lock.contains(&item.slot) || (!only.is_empty() && !only.contains(&item.slot));
+            let keep = restrict.include_items.contains(&item.id) ||
+                (!restrict.exclude_items.contains(&item.id) && (item.equipped || !fixed));
+
+            if keep { vec![item.clone()] } else { Vec::new() }
+        });
+        self.items = items;
+
+        Ok(())
+    }
+
+    /// Map slot names to the slots of the item map.
+    fn slots_of(&self, names: &[String]) -> Result<Vec<Slot>, Error> {
+        let mut slots: Vec<Slot> = Vec::new();
+
+        for name in names.iter() {
+            let slot = match Slot::from_str(name.trim()) {
+                Ok(slot) => slot,
+                Err(_) => {
+                    return Err(Error::new(ErrorKind::InvalidInput, format!("Unknown slot {}", name)));
+                }
+            };
+
+            if self.config.simcraft.replace_weaponhand_enum &&
+            (slot.slot == ESlot::MainHand || slot.slot == ESlot::OffHand) {
+                slots.push(Slot::from_enum(ESlot::WeaponHand));
+            } else {
+                slots.push(slot);
+            }
+        }
+
+        Ok(slots)
+    }
+
+    /// Replace every item by one variant per azerite selection and per
+    /// enchantment and gem candidate of its slot. So the permutation covers
+    /// these choices, too.
+    fn expand_candidates(&mut self) {
+        let mut items = mem::replace(&mut self.items, ItemMap::new());
+        items.expand(|item| self.candidate_variants(item));
+        self.items = items;
+    }
+
+    /// Build all variants of an item. Every socket of an item gets the
+    /// same gem. Only the first variant of an equipped item is equipped,
+    /// the others are candidates of the upgrade search.
+    fn candidate_variants(&self, item: &Item) -> Vec<Item> {
+        let mut variants: Vec<Item> = match self.config.azerite.iter().find(|a| a.id == item.id) {
+            Some(azerite) => Simcraft::azerite_selections(azerite).iter().map(|powers| {
+                let mut variant = item.clone();
+                variant.azerite_powers = powers.clone();
+                variant
+            }).collect(),
+            None => vec![item.clone()]
+        };
+
+        if let Some(enchant) = self.get_replaced_enchantment(&item.slot) {
+            if !enchant.candidates.is_empty() {
+                variants = variants.iter().flat_map(|v| enchant.candidates.iter().map(move |id| {
+                    let mut variant = v.clone();
+                    variant.enchant_id = *id;
+                    variant
+                })).collect();
+            }
+
+            if !enchant.gems.is_empty() && !item.gem_id.is_empty() {
+                let sockets = item.gem_id.split('/').count();
+                variants = variants.iter().flat_map(|v| enchant.gems.iter().map(move |gem| {
+                    let mut variant = v.clone();
+                    variant.gem_id = vec![gem.to_string(); sockets].join("/");
+                    variant
+                })).collect();
+            }
+        }
+
+        for variant in variants.iter_mut().skip(1) {
+            variant.equipped = false;
+        }
+
+        variants
+    }
+
+    /// All valid trait selections of an azerite item. Every selection
+    /// takes one trait of each ring.
+    fn azerite_selections(azerite: &Azerite) -> Vec<String> {
+        let mut selections: Vec<String> = vec![String::new()];
+
+        for ring in azerite.rings.iter().filter(|r| !r.is_empty()) {
+            let mut next: Vec<String> = Vec::new();
+            for selection in selections.iter() {
+                for power in ring.iter() {
+                    if selection.is_empty() {
+                        next.push(power.to_string());
+                    } else {
+                        next.push(format!("{}/{}", selection, power));
+                    }
+                }
+            }
+
+            selections = next;
+        }
+
+        selections
+    }
+
+    /// Describe a stack for the report. These are the azerite traits of
+    /// all items and the number of pieces of every set.
+    fn report_info(&self, stack: &[Item]) -> String {
+        // name the variant, if there are several ones of an item
+        let mut info: Vec<String> = Vec::new();
+        for item in stack.iter() {
+            if self.items.has_variants(item) {
+                info.push(format!("{} {}", item.slot.get_name(), item.variant()));
+            } else if !item.azerite_powers.is_empty() {
+                info.push(format!("{} {}: {}", item.slot.get_name(), item.id, item.azerite_powers));
+            }
+        }
+
+        for set in self.config.sets.iter() {
+            let pieces = stack.iter().filter(|i| set.items.contains(&i.id)).count();
+            if pieces > 0 {
+                info.push(format!("{}: {} pieces", set.name, pieces));
+            }
+        }
+
+        info.join(", ")
+    }
+
+    /// Describe a profileset for the report. These are all its items
+    /// followed by the info of report_info.
+    fn profileset_info(&self, stack: &[Item]) -> String {
+        let items: Vec<String> = stack.iter()
+            .map(|item| format!("{} {}", item.slot.name, item.description()))
+            .collect();
+        let info = self.report_info(stack);
+
+        if info.is_empty() {
+            format!("Items: {}", items.join(", "))
+        } else {
+            format!("Items: {} - {}", items.join(", "), info)
+        }
+    }
+
     fn next_slot(current: ESlot) -> Option<ESlot> {
         match current {
             ESlot::Head => Some(ESlot::Neck),
@@ -594,28 +1574,38 @@ impl Simcraft {
         }
     }
 
-    fn has_multiple_of_them(&self, stack: &Vec<Item>) -> bool {
+    /// Is an item unique-equipped? Items without an own flag use the
+    /// default of the configuration.
+    fn is_unique(&self, item: &Item) -> bool {
+        match self.config.unique.items.iter().find(|u| u.id == item.id) {
+            Some(u) => u.unique,
+            None => self.config.unique.by_default
+        }
+    }
 
-        // step through all limits
-        for i in &self.config.limits {
-            let mut count = 0u32;
+    /// Can both items be used together in a pair slot? Variants of the
+    /// same item never. Two copies of an item only if it is not
+    /// unique-equipped.
+    fn can_pair(&self, first: &Item, second: &Item) -> bool {
+        first.origin != second.origin && !(first.id == second.id && self.is_unique(first))
+    }
 
-            // check items
-            for s in stack.iter() {
-                // is in limit list
-                for l in &i.items {
-                    if *l == s.id {
-                        count += 1;
-                    }
+    /// Are weapon types or ordered weapons configured? Without them all
+    /// weapons are combined as unordered pairs.
+    fn has_weapon_types(&self) -> bool {
+        let weapons = &self.config.weapons;
+        !weapons.two_hand.is_empty() || !weapons.off_hand.is_empty() || weapons.titans_grip || weapons.ordered
+    }
 
-                    // limit arrived
-                    if count >= i.max {
-                        return true;
-                    }
-                }
-            }
-        }
+    /// A two-handed weapon occupies both hands. With titans grip it
+    /// is handled like a one-handed weapon.
+    fn is_two_handed(&self, item: &Item) -> bool {
+        !self.config.weapons.titans_grip && self.config.weapons.two_hand.contains(&item.id)
+    }
 
-        false
+    /// Off-hands and shields can only be used in the off hand.
+    fn is_off_hand(&self, item: &Item) -> bool {
+        self.config.weapons.off_hand.contains(&item.id)
     }
+
 }
@@ -0,0 +1,759 @@
+use item::Item;
+use slot::{Slot, ESlot};
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use super::Simcraft;
+
+
+/// A single step of the permutation. Every step covers all items of
+/// one slot. Rings, trinkets and weapons are combined as pairs. With
+/// weapon types the weapons have a list of all valid choices instead.
+struct Step<'a> {
+    items: &'a Vec<Item>,
+    double: bool,
+    weapons: Option<Vec<(usize, Option<usize>)>>,
+    size: u64
+}
+
+
+/// This is the space of all combinations. Every combination has a
+/// unique index. The index is split into one digit per step. Pairs are
+/// stored as one digit.
+///
+/// Nothing is stored here. A combination will be build if it is
+/// requested. Only the usage of every choice for the counters of the
+/// rules is known. With it a combination is dropped as soon as a step
+/// breaks a rule. The number of valid completions of every reached
+/// counter state is cached. So the valid combinations are numbered, too.
+pub struct Permutation<'a> {
+    simc: &'a Simcraft,
+    steps: Vec<Step<'a>>,
+    size: u64,
+    usage: Vec<Vec<Option<Vec<u32>>>>,
+    remaining: Vec<Vec<u32>>,
+    completions: Mutex<HashMap<(usize, Vec<u32>), u64>>
+}
+
+impl<'a> Permutation<'a> {
+    pub fn new(simc: &'a Simcraft) -> Permutation<'a> {
+        let mut steps: Vec<Step> = Vec::new();
+        let mut slot = Some(ESlot::Head);
+
+        // step through all slots. Empty ones will be ignored
+        while let Some(s) = slot {
+            if let Some(items) = simc.items.get_slot(s) {
+                let double = s == ESlot::Finger || s == ESlot::Trinket || s == ESlot::WeaponHand;
+                let len = items.len() as u64;
+                let weapons = if s == ESlot::WeaponHand && simc.has_weapon_types() {
+                    Some(Permutation::weapon_choices(simc, items))
+                } else {
+                    None
+                };
+
+                let size = match weapons {
+                    Some(ref choices) => choices.len() as u64,
+                    None => if double { (len * len - len) / 2 } else { len }
+                };
+
+                steps.push(Step {
+                    items,
+                    double,
+                    weapons,
+                    size
+                });
+            }
+
+            slot = Simcraft::next_slot(s);
+        }
+
+        let mut size = 1u64;
+        for step in &steps {
+            size *= step.size;
+        }
+
+        let mut permutation = Permutation {
+            simc,
+            steps,
+            size,
+            usage: Vec::new(),
+            remaining: Vec::new(),
+            completions: Mutex::new(HashMap::new())
+        };
+
+        // the usage of every choice and the most all following steps can
+        // add to each counter
+        let usage: Vec<Vec<Option<Vec<u32>>>> = permutation.steps.iter()
+            .map(|s| permutation.step_usage(s))
+            .collect();
+        let counters = simc.rules.size();
+        let mut remaining: Vec<Vec<u32>> = vec![vec![0; counters]; usage.len() + 1];
+        for n in (0..usage.len()).rev() {
+            for l in 0..counters {
+                let most = usage[n].iter().flatten().map(|u| u[l]).max().unwrap_or(0);
+                remaining[n][l] = remaining[n + 1][l] + most;
+            }
+        }
+
+        permutation.usage = usage;
+        permutation.remaining = remaining;
+        permutation
+    }
+
+    /// The number of all combinations. This includes combinations
+    /// that breaks the rules. Use this only as upper bound.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The number of choices for every step. Pairs are one choice.
+    pub fn step_sizes(&self) -> Vec<u64> {
+        self.steps.iter().map(|s| s.size).collect()
+    }
+
+    /// Split an index into one digit per step.
+    pub fn digits(&self, index: u64) -> Vec<u64> {
+        let mut digits: Vec<u64> = vec![0; self.steps.len()];
+        let mut index = index;
+
+        // last step is the lowest digit
+        for (n, step) in self.steps.iter().enumerate().rev() {
+            digits[n] = index % step.size;
+            index /= step.size;
+        }
+
+        digits
+    }
+
+    /// Join the digits of all steps to an index.
+    pub fn index(&self, digits: &[u64]) -> u64 {
+        let mut index = 0u64;
+
+        for (step, digit) in self.steps.iter().zip(digits.iter()) {
+            index = index * step.size + digit;
+        }
+
+        index
+    }
+
+    /// Count all combinations that follows the rules. This is done without
+    /// building a single combination. Every step is reduced to the number of
+    /// items it adds to each limit. Only these numbers are combined.
+    ///
+    /// Partial combinations that cannot reach the minimum of a limit with
+    /// the remaining steps are dropped early.
+    pub fn count(&self) -> u64 {
+        self.completions(0, &vec![0; self.simc.rules.size()])
+    }
+
+    /// Count all combinations that follows the rules and have none of the
+    /// skipped items.
+    pub fn count_without<F>(&self, skip: F) -> u64
+        where F: Fn(&Item) -> bool
+    {
+        let rules = &self.simc.rules;
+        let choices: Vec<HashMap<Vec<u32>, u64>> = self.steps.iter().map(|s| self.count_choices(s, &skip)).collect();
+
+        // the most items all following steps can add to each limit
+        let mut remaining: Vec<Vec<u32>> = vec![vec![0; rules.size()]; self.steps.len() + 1];
+        for n in (0..self.steps.len()).rev() {
+            for l in 0..rules.size() {
+                let most = choices[n].keys().map(|usage| usage[l]).max().unwrap_or(0);
+                remaining[n][l] = remaining[n + 1][l] + most;
+            }
+        }
+
+        // state => number of partial combinations with these limit counters
+        let mut states: HashMap<Vec<u32>, u64> = HashMap::new();
+        states.insert(vec![0; rules.size()], 1);
+
+        for (n, step_choices) in choices.iter().enumerate() {
+            let mut next: HashMap<Vec<u32>, u64> = HashMap::new();
+
+            for (state, count) in &states {
+                for (usage, choice_count) in step_choices {
+                    let merged: Vec<u32> = state.iter().zip(usage.iter()).map(|(a, b)| a + b).collect();
+
+                    // skip everything that is already over the limit or
+                    // cannot reach the minimum anymore
+                    if rules.over_max(&merged) || rules.out_of_reach(&merged, &remaining[n + 1]) {
+                        continue;
+                    }
+
+                    *next.entry(merged).or_insert(0) += count * choice_count;
+                }
+            }
+
+            states = next;
+        }
+
+        states.iter().filter(|(state, _)| rules.accepts(state)).map(|(_, count)| count).sum()
+    }
+
+    /// Build the combination stored at index. Returns None if this
+    /// combination breaks a rule.
+    pub fn get(&self, index: u64) -> Option<Vec<Item>> {
+        let digits = self.digits(index);
+        if self.cut(&digits).is_some() {
+            return None;
+        }
+
+        Some(self.build(&digits))
+    }
+
+    /// The digits of the valid combination with this rank. The valid
+    /// combinations are ranked in the order of their index.
+    fn nth(&self, rank: u64) -> Vec<u64> {
+        let rules = &self.simc.rules;
+        let mut rank = rank;
+        let mut counts: Vec<u32> = vec![0; rules.size()];
+        let mut digits: Vec<u64> = Vec::new();
+
+        for n in 0..self.steps.len() {
+            for (digit, usage) in self.usage[n].iter().enumerate() {
+                let merged = match self.merge(n, &counts, usage) {
+                    Some(merged) => merged,
+                    None => continue
+                };
+
+                // skip all combinations of this branch at once
+                let completions = self.completions(n + 1, &merged);
+                if rank < completions {
+                    digits.push(digit as u64);
+                    counts = merged;
+                    break;
+                }
+
+                rank -= completions;
+            }
+        }
+
+        digits
+    }
+
+    /// Number of valid combinations that start with a partial combination.
+    /// counts are the counters of all steps before step n.
+    fn completions(&self, n: usize, counts: &[u32]) -> u64 {
+        if n == self.steps.len() {
+            return if self.simc.rules.accepts(counts) { 1 } else { 0 };
+        }
+
+        let key = (n, counts.to_vec());
+        if let Some(completions) = self.completions.lock().unwrap().get(&key) {
+            return *completions;
+        }
+
+        let completions = self.usage[n].iter()
+            .filter_map(|usage| self.merge(n, counts, usage))
+            .map(|merged| self.completions(n + 1, &merged))
+            .sum();
+
+        self.completions.lock().unwrap().insert(key, completions);
+        completions
+    }
+
+    /// Add the usage of a choice of step n to the counters. Returns None
+    /// if the choice is not possible or breaks a rule.
+    fn merge(&self, n: usize, counts: &[u32], usage: &Option<Vec<u32>>) -> Option<Vec<u32>> {
+        let rules = &self.simc.rules;
+        let merged: Vec<u32> = counts.iter().zip(usage.as_ref()?.iter()).map(|(a, b)| a + b).collect();
+
+        if rules.over_max(&merged) || rules.out_of_reach(&merged, &self.remaining[n + 1]) {
+            None
+        } else {
+            Some(merged)
+        }
+    }
+
+    /// Build the combination of these digits.
+    fn build(&self, digits: &[u64]) -> Vec<Item> {
+        let mut stack: Vec<Item> = Vec::new();
+        for (step, digit) in self.steps.iter().zip(digits.iter()) {
+            if let Some(ref choices) = step.weapons {
+                let (main, off) = choices[*digit as usize];
+
+                let mut item = step.items[main].clone();
+                item.slot = Slot::get_real_slot(&item.slot, 1).unwrap();
+                stack.push(item);
+
+                if let Some(off) = off {
+                    let mut item = step.items[off].clone();
+                    item.slot = Slot::get_real_slot(&item.slot, 2).unwrap();
+                    stack.push(item);
+                }
+            } else if step.double {
+                let (first, second) = Permutation::split_pair(*digit, step.items.len());
+                let slot1 = &step.items[first];
+                let slot2 = &step.items[second];
+
+                let mut item = slot1.clone();
+                item.slot = Slot::get_real_slot(&slot1.slot, 1).unwrap();
+                stack.push(item);
+
+                let mut item = slot2.clone();
+                item.slot = Slot::get_real_slot(&slot2.slot, 2).unwrap();
+                stack.push(item);
+            } else {
+                stack.push(step.items[*digit as usize].clone());
+            }
+        }
+
+        stack
+    }
+
+    /// Check the rules step by step. A partial combination is cut as soon
+    /// as a counter is over its maximum or cannot reach its minimum with
+    /// the remaining steps. So the items are only cloned for combinations
+    /// that follows the rules.
+    ///
+    /// Returns the step that breaks a rule. None if all rules are fulfilled.
+    fn cut(&self, digits: &[u64]) -> Option<usize> {
+        let rules = &self.simc.rules;
+        let mut counts: Vec<u32> = vec![0; rules.size()];
+
+        for (n, digit) in digits.iter().enumerate() {
+            // a pair of the same item is no choice
+            let usage = match self.usage[n][*digit as usize] {
+                Some(ref usage) => usage,
+                None => return Some(n)
+            };
+            for (count, u) in counts.iter_mut().zip(usage.iter()) {
+                *count += u;
+            }
+
+            if rules.over_max(&counts) || rules.out_of_reach(&counts, &self.remaining[n + 1]) {
+                return Some(n);
+            }
+        }
+
+        if rules.accepts(&counts) {
+            None
+        } else {
+            Some(digits.len().saturating_sub(1))
+        }
+    }
+
+    /// The usage of every choice of a step. A choice that is not possible,
+    /// like the same item on both slots, has none.
+    fn step_usage(&self, step: &Step) -> Vec<Option<Vec<u32>>> {
+        if let Some(ref weapons) = step.weapons {
+            weapons.iter().map(|(main, off)| {
+                let mut usage = self.usage(&step.items[*main], 1);
+                if let Some(off) = off {
+                    for (a, b) in usage.iter_mut().zip(self.usage(&step.items[*off], 2).iter()) {
+                        *a += b;
+                    }
+                }
+
+                Some(usage)
+            }).collect()
+        } else if step.double {
+            (0..step.size).map(|digit| {
+                let (first, second) = Permutation::split_pair(digit, step.items.len());
+                if !self.simc.can_pair(&step.items[first], &step.items[second]) {
+                    return None;
+                }
+
+                Some(self.usage(&step.items[first], 1).iter()
+                    .zip(self.usage(&step.items[second], 2).iter())
+                    .map(|(a, b)| a + b)
+                    .collect())
+            }).collect()
+        } else {
+            step.items.iter().map(|item| Some(self.usage(item, 0))).collect()
+        }
+    }
+
+    /// Create a new random order to walk through all valid combinations.
+    pub fn random_order<R: Rng>(&self, rng: &mut R) -> Order {
+        let size = self.count();
+        let mut multiplier = 1u64;
+        let mut offset = 0u64;
+
+        if size > 1 {
+            // multiplier must be coprime to the size. Otherwise some
+            // combinations will be visited twice.
+            loop {
+                multiplier = rng.gen_range(1, size);
+                if Permutation::gcd(multiplier, size) == 1 {
+                    break;
+                }
+            }
+
+            offset = rng.gen_range(0, size);
+        }
+
+        Order {
+            multiplier,
+            offset,
+            size
+        }
+    }
+
+    /// The natural order of all valid combinations.
+    pub fn sequential_order(&self) -> Order {
+        Order {
+            multiplier: 1,
+            offset: 0,
+            size: self.count()
+        }
+    }
+
+    /// Walk through all valid combinations in the given order.
+    pub fn iter(&'a self, order: &Order) -> Iter<'a> {
+        Iter {
+            permutation: self,
+            multiplier: order.multiplier,
+            offset: order.offset,
+            size: order.size,
+            position: 0
+        }
+    }
+
+    /// Group all choices of a step by the number of items they add
+    /// to each counter of the rules. Choices with a skipped item are left out.
+    fn count_choices<F>(&self, step: &Step, skip: &F) -> HashMap<Vec<u32>, u64>
+        where F: Fn(&Item) -> bool
+    {
+        let mut choices: HashMap<Vec<u32>, u64> = HashMap::new();
+
+        if let Some(ref weapons) = step.weapons {
+            for (main, off) in weapons.iter() {
+                if skip(&step.items[*main]) || off.is_some_and(|off| skip(&step.items[off])) {
+                    continue;
+                }
+
+                let mut usage = self.usage(&step.items[*main], 1);
+                if let Some(off) = off {
+                    for (a, b) in usage.iter_mut().zip(self.usage(&step.items[*off], 2).iter()) {
+                        *a += b;
+                    }
+                }
+
+                *choices.entry(usage).or_insert(0) += 1;
+            }
+        } else if step.double {
+            for (n, first) in step.items.iter().enumerate() {
+                for second in step.items.iter().skip(n + 1) {
+                    // cannot add the same item on both slots
+                    if !self.simc.can_pair(first, second) || skip(first) || skip(second) {
+                        continue;
+                    }
+
+                    let usage: Vec<u32> = self.usage(first, 1).iter()
+                        .zip(self.usage(second, 2).iter())
+                        .map(|(a, b)| a + b)
+                        .collect();
+                    *choices.entry(usage).or_insert(0) += 1;
+                }
+            }
+        } else {
+            for item in step.items.iter().filter(|i| !skip(i)) {
+                *choices.entry(self.usage(item, 0)).or_insert(0) += 1;
+            }
+        }
+
+        choices
+    }
+
+    /// The usage of an item at its position of a pair. Part 0 stands
+    /// for a single slot. The rules may depend on the position.
+    fn usage(&self, item: &Item, part: u8) -> Vec<u32> {
+        if part == 0 {
+            return self.simc.rules.usage(item);
+        }
+
+        let mut item = item.clone();
+        item.slot = Slot::get_real_slot(&item.slot, part).unwrap();
+        self.simc.rules.usage(&item)
+    }
+
+    /// All valid weapon choices. A two-handed weapon is used alone. Off-hands
+    /// are only used in the off hand. Two weapons for both hands are
+    /// combined as unordered pairs. If ordered is set, both orders are used.
+    /// So A in the main hand with B in the off hand is compared to B/A.
+    fn weapon_choices(simc: &Simcraft, items: &[Item]) -> Vec<(usize, Option<usize>)> {
+        let mut choices: Vec<(usize, Option<usize>)> = Vec::new();
+
+        for (main, first) in items.iter().enumerate() {
+            if simc.is_off_hand(first) {
+                continue;
+            }
+
+            if simc.is_two_handed(first) {
+                choices.push((main, None));
+                continue;
+            }
+
+            for (off, second) in items.iter().enumerate() {
+                // cannot add the same item on both slots
+                if off == main || !simc.can_pair(first, second) || simc.is_two_handed(second) {
+                    continue;
+                }
+
+                // both can be used in the main hand. So take them only once.
+                if !simc.config.weapons.ordered && !simc.is_off_hand(second) && off < main {
+                    continue;
+                }
+
+                choices.push((main, Some(off)));
+            }
+        }
+
+        choices
+    }
+
+    /// Map the digit of a pair step to the index of both items. The
+    /// first item has always the lower index.
+    fn split_pair(digit: u64, len: usize) -> (usize, usize) {
+        let mut digit = digit as usize;
+
+        for first in 0..len {
+            let count = len - first - 1;
+            if digit < count {
+                return (first, first + 1 + digit);
+            }
+
+            digit -= count;
+        }
+
+        (0, 0)
+    }
+
+    fn gcd(a: u64, b: u64) -> u64 {
+        if b == 0 { a } else { Permutation::gcd(b, a % b) }
+    }
+}
+
+
+/// The order of all valid combinations. It is given by
+/// rank = (multiplier * position + offset) % size. size is the number of
+/// valid combinations. Because multiplier and size are coprime every
+/// combination is visited exactly once.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Order {
+    pub multiplier: u64,
+    pub offset: u64,
+    pub size: u64
+}
+
+
+/// Iterator over all valid combinations. Returns the index and the
+/// items of every combination. Combinations that break a rule are never
+/// visited.
+pub struct Iter<'a> {
+    permutation: &'a Permutation<'a>,
+    multiplier: u64,
+    offset: u64,
+    size: u64,
+    position: u64
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (u64, Vec<Item>);
+
+    fn next(&mut self) -> Option<(u64, Vec<Item>)> {
+        if self.position >= self.size {
+            return None;
+        }
+
+        let rank = ((self.multiplier as u128 * self.position as u128 + self.offset as u128)
+            % self.size as u128) as u64;
+        self.position += 1;
+
+        let digits = self.permutation.nth(rank);
+        Some((self.permutation.index(&digits), self.permutation.build(&digits)))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use configuration::{Configuration, Constraint, Limit, Set, UniqueItem};
+    use constraint::Rules;
+    use item_map::ItemMap;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use report::Generator;
+    use runtime::Runtime;
+    use template::Template;
+
+    /// A configuration without any rules.
+    fn config() -> Configuration {
+        let mut config = Configuration::load("example.config.json").unwrap();
+        config.limits.clear();
+        config.sets.clear();
+        config.constraints.clear();
+        config.unique.by_default = true;
+        config.unique.items.clear();
+        config.unique.categories.clear();
+        config.weapons = Default::default();
+        config
+    }
+
+    /// Build a run of these items. Every item is given by its slot and id.
+    fn simcraft(config: Configuration, items: &[(ESlot, u32)]) -> Simcraft {
+        let mut map = ItemMap::new();
+        for (slot, id) in items {
+            let mut item = Item::new();
+            item.id = *id;
+            item.slot = Slot::from_enum(*slot);
+            item.name = format!("item_{}", id);
+            map.push(&item.slot.clone(), &item);
+        }
+
+        let rules = Rules::new(&config, &map).unwrap();
+        let report = Generator::new(&config, "reports");
+
+        Simcraft {
+            config,
+            items: map,
+            template: Template::default(),
+            output_dir: String::new(),
+            report_dir: String::new(),
+            compile_dir: String::new(),
+            log_dir: String::new(),
+            report,
+            spec: String::new(),
+            talents: Vec::new(),
+            level: 120,
+            resume: false,
+            rules,
+            seed: 0,
+            runtime: Runtime::new(15.0)
+        }
+    }
+
+    /// Items of every kind of step. 2 heads, 4 rings, 3 trinkets and
+    /// 4 weapons.
+    fn items() -> Vec<(ESlot, u32)> {
+        vec![
+            (ESlot::Head, 1), (ESlot::Head, 2),
+            (ESlot::Finger, 10), (ESlot::Finger, 11), (ESlot::Finger, 12), (ESlot::Finger, 12),
+            (ESlot::Trinket, 20), (ESlot::Trinket, 21), (ESlot::Trinket, 22),
+            (ESlot::WeaponHand, 30), (ESlot::WeaponHand, 31), (ESlot::WeaponHand, 32), (ESlot::WeaponHand, 33)
+        ]
+    }
+
+    /// Count all valid combinations the slow way. Every index is built
+    /// without an early cut and checked as a whole.
+    fn brute_force(permutation: &Permutation) -> u64 {
+        (0..permutation.size())
+            .map(|index| permutation.digits(index))
+            .filter(|digits| digits.iter().enumerate().all(|(n, d)| permutation.usage[n][*d as usize].is_some()))
+            .filter(|digits| !permutation.simc.rules.breaks(&permutation.build(digits)))
+            .count() as u64
+    }
+
+    /// The exact count must match the combinations the iterator returns
+    /// in both orders.
+    fn check(simc: &Simcraft) -> u64 {
+        let permutation = Permutation::new(simc);
+        let count = permutation.count();
+
+        assert_eq!(count, brute_force(&permutation));
+        assert_eq!(count, permutation.count_without(|_| false));
+        let sequential: Vec<u64> = permutation.iter(&permutation.sequential_order()).map(|(index, _)| index).collect();
+        assert_eq!(count, sequential.len() as u64);
+        assert!(sequential.windows(2).all(|w| w[0] < w[1]));
+        assert!(sequential.iter().all(|index| permutation.get(*index).is_some()));
+
+        // a random order visits the same combinations
+        let mut rng = StdRng::seed_from_u64(7);
+        let order = permutation.random_order(&mut rng);
+        let mut random: Vec<u64> = permutation.iter(&order).map(|(index, _)| index).collect();
+        random.sort();
+        assert_eq!(random, sequential);
+
+        count
+    }
+
+    #[test]
+    fn count_without_rules() {
+        // rings: 6 pairs minus 12/12, trinkets: 3, weapons: 6
+        assert_eq!(check(&simcraft(config(), &items())), 2 * 5 * 3 * 6);
+    }
+
+    #[test]
+    fn count_unique_pairs() {
+        let mut config = config();
+        config.unique.items.push(UniqueItem { id: 12, unique: false });
+        assert_eq!(check(&simcraft(config, &items())), 2 * 6 * 3 * 6);
+
+        let mut config = self::config();
+        config.unique.by_default = false;
+        assert_eq!(check(&simcraft(config, &items())), 2 * 6 * 3 * 6);
+    }
+
+    #[test]
+    fn count_limits() {
+        let mut config = config();
+        config.limits.push(Limit { min: 1, max: 2, items: vec![1, 10, 20, 30] });
+        config.limits.push(Limit { min: 0, max: 1, items: vec![11, 21] });
+        check(&simcraft(config, &items()));
+    }
+
+    #[test]
+    fn count_sets() {
+        let mut config = config();
+        config.sets.push(Set { name: String::from("set"), min: 2, max: 4, items: vec![2, 12, 22, 32] });
+        check(&simcraft(config, &items()));
+    }
+
+    #[test]
+    fn count_constraints() {
+        let mut config = config();
+        config.constraints.push(Constraint { kind: String::from("required"), items: vec![21], max: u32::MAX, ..Default::default() });
+        config.constraints.push(Constraint { kind: String::from("implies"), items: vec![2], then: vec![10], max: u32::MAX, ..Default::default() });
+        config.constraints.push(Constraint { kind: String::from("exclusive"), items: vec![11, 31], max: 1, ..Default::default() });
+        config.constraints.push(Constraint { kind: String::from("forbidden"), items: vec![33], slots: vec![String::from("main_hand")], ..Default::default() });
+        config.weapons.ordered = true;
+        check(&simcraft(config, &items()));
+    }
+
+    #[test]
+    fn count_ordered_weapons() {
+        let mut config = config();
+        config.weapons.two_hand.push(30);
+        config.weapons.off_hand.push(33);
+        assert_eq!(check(&simcraft(config.clone(), &items())), 2 * 5 * 3 * (1 + 1 + 2));
+
+        config.weapons.ordered = true;
+        assert_eq!(check(&simcraft(config.clone(), &items())), 2 * 5 * 3 * (1 + 2 + 2));
+
+        config.constraints.push(Constraint { kind: String::from("count"), slots: vec![String::from("off_hand")], min: 1, max: 1, ..Default::default() });
+        assert_eq!(check(&simcraft(config, &items())), 2 * 5 * 3 * (2 + 2));
+    }
+
+    #[test]
+    fn split_pair_covers_all_pairs() {
+        let pairs: Vec<(usize, usize)> = (0..10).map(|digit| Permutation::split_pair(digit, 5)).collect();
+
+        let mut expected: Vec<(usize, usize)> = Vec::new();
+        for first in 0..5 {
+            for second in (first + 1)..5 {
+                expected.push((first, second));
+            }
+        }
+
+        assert_eq!(pairs, expected);
+    }
+
+    #[test]
+    fn digits_and_index() {
+        let simc = simcraft(config(), &items());
+        let permutation = Permutation::new(&simc);
+        assert_eq!(permutation.step_sizes(), vec![2, 6, 3, 6]);
+
+        for index in 0..permutation.size() {
+            let digits = permutation.digits(index);
+            assert!(digits.iter().zip(permutation.step_sizes().iter()).all(|(d, s)| d < s));
+            assert_eq!(permutation.index(&digits), index);
+        }
+
+        // the last step is the lowest digit
+        assert_eq!(permutation.digits(1), vec![0, 0, 0, 1]);
+        assert_eq!(permutation.digits(6), vec![0, 0, 1, 0]);
+    }
+}
@@ -0,0 +1,297 @@
+use std::result::{Result};
+use std::io::{Error, ErrorKind};
+use std::collections::HashMap;
+use configuration::Configuration;
+use statistic::Data;
+
+
+/// A pruning strategy decides which items are bad enough to skip all
+/// combinations with them. It is called after every simulation and marks
+/// the bad items as ignored.
+pub trait Strategy: Send + Sync {
+    /// Name of the strategy in the configuration.
+    fn name(&self) -> &str;
+
+    /// Rate all items. min_dps and max_dps is the range of the best
+    /// combinations so far.
+    fn rate(&self, items: &mut [Data], min_dps: f32, max_dps: f32);
+}
+
+
+/// Create the strategy that is configured by statistic.strategy.
+pub fn create(config: &Configuration, iterations: u64, total_items: usize) -> Result<Box<dyn Strategy>, Error> {
+    let statistic = &config.statistic;
+
+    match statistic.strategy.as_str() {
+        "rating" => Ok(Box::new(Rating::new(config, iterations, total_items))),
+        "none" => Ok(Box::new(NoPruning)),
+        "percentile" => {
+            if statistic.percentile < 0.0 || statistic.percentile > 100.0 {
+                return Err(Error::new(ErrorKind::InvalidData,
+                    format!("Percentile must be between 0 and 100, got {}", statistic.percentile)));
+            }
+
+            Ok(Box::new(Percentile {
+                percentile: statistic.percentile,
+                min_seen: statistic.min_seen
+            }))
+        },
+        "confidence" => {
+            if statistic.confidence <= 0.0 {
+                return Err(Error::new(ErrorKind::InvalidData,
+                    "The strategy confidence needs a confidence level"));
+            }
+
+            Ok(Box::new(Confidence {
+                z: z_score(statistic.confidence),
+                margin: statistic.margin,
+                min_seen: statistic.min_seen
+            }))
+        },
+        "slot_rank" => Ok(Box::new(SlotRank {
+            rank: statistic.rank.max(1),
+            min_seen: statistic.min_seen
+        })),
+        strategy => {
+            Err(Error::new(ErrorKind::InvalidData,
+                format!("Unknown strategy {}. Use rating, none, percentile, confidence or slot_rank", strategy)))
+        }
+    }
+}
+
+
+/// Rate every item by its average dps within the range of the best
+/// combinations. The tolerance shrinks the more often an item was seen.
+/// With a confidence level an item must also be worse than the best
+/// combination within the error of simc.
+pub struct Rating {
+    tolerance: f32,
+    threshold: i32,
+    modifier: f32,
+    z: f32,
+    margin: f32
+}
+
+impl Rating {
+    pub fn new(config: &Configuration, iterations: u64, total_items: usize) -> Rating {
+        Rating {
+            tolerance: config.statistic.tolerance,
+            threshold: config.statistic.threshold,
+            modifier: ((iterations as f64).sqrt() / (total_items as f64 / 16.0)) as f32,
+            z: z_score(config.statistic.confidence),
+            margin: config.statistic.margin
+        }
+    }
+}
+
+impl Strategy for Rating {
+    fn name(&self) -> &str {
+        "rating"
+    }
+
+    fn rate(&self, items: &mut [Data], min_dps: f32, max_dps: f32) {
+        let best = best_lower_bound(items, self.z, self.margin);
+
+        for data in items.iter_mut() {
+            // could be negativ. So, we need more then the minimum dps.
+            let mut tolerance = (1.0 + (self.tolerance / 100.0)) + (1.0 - (data.seen as f32 / self.modifier)).sin();
+            if tolerance < 1.0 {
+                tolerance = 1.0;
+            }
+
+            // calc minimum dps with tolerance
+            let min = min_dps / tolerance;
+
+            // rate item
+            data.rating = ((data.dps_avg - min) / (max_dps - min) * 100.0) as i32;
+            //println!("Rating: Item({}) -> Rating({}) / DPS({} / {}) Avg DPS({}) | Min({}), Seen({}), Tol({})", 
+            //    data.item_id, data.rating, min_dps, max_dps, data.dps_avg, min, data.seen, tolerance);
+
+            // without a confidence level every bad rated item is ignored
+            data.ignored = data.rating < self.threshold &&
+                (self.z <= 0.0 || data.dps_avg + self.z * data.error() < best);
+        }
+    }
+}
+
+
+/// Never skip a combination.
+pub struct NoPruning;
+
+impl Strategy for NoPruning {
+    fn name(&self) -> &str {
+        "none"
+    }
+
+    fn rate(&self, items: &mut [Data], _min_dps: f32, _max_dps: f32) {
+        for data in items.iter_mut() {
+            data.ignored = false;
+        }
+    }
+}
+
+
+/// Skip the items whose average dps is in the lowest percentile of all
+/// items. The best items of every slot are always kept. Otherwise an
+/// item without alternative could skip all combinations.
+pub struct Percentile {
+    percentile: f32,
+    min_seen: i32
+}
+
+impl Strategy for Percentile {
+    fn name(&self) -> &str {
+        "percentile"
+    }
+
+    fn rate(&self, items: &mut [Data], _min_dps: f32, _max_dps: f32) {
+        let mut dps: Vec<f32> = items.iter()
+            .filter(|d| d.seen >= self.min_seen)
+            .map(|d| d.dps_avg)
+            .collect();
+        dps.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        // the number of items below the cut-off
+        let below = (dps.len() as f32 * self.percentile / 100.0).floor() as usize;
+        let cut_off = if below == 0 { None } else { dps.get(below).cloned() };
+        let slots = rank_slots(items);
+
+        for data in items.iter_mut() {
+            let best = slots[&data.slot].get(positions(&data.slot) - 1).cloned();
+            data.ignored = match (cut_off, best) {
+                (Some(cut_off), Some(best)) => data.seen >= self.min_seen && data.dps_avg < cut_off && data.dps_avg < best,
+                _ => false
+            };
+        }
+    }
+}
+
+
+/// Skip the items whose upper confidence bound is below the lower bound
+/// of the best combination.
+pub struct Confidence {
+    z: f32,
+    margin: f32,
+    min_seen: i32
+}
+
+impl Strategy for Confidence {
+    fn name(&self) -> &str {
+        "confidence"
+    }
+
+    fn rate(&self, items: &mut [Data], _min_dps: f32, _max_dps: f32) {
+        let best = best_lower_bound(items, self.z, self.margin);
+
+        for data in items.iter_mut() {
+            data.ignored = data.seen >= self.min_seen && data.dps_avg + self.z * data.error() < best;
+        }
+    }
+}
+
+
+/// Rank the items of every slot by their average dps. Only the best ones
+/// of a slot are kept. Slots with two positions, like rings or trinkets,
+/// keep twice as many.
+pub struct SlotRank {
+    rank: usize,
+    min_seen: i32
+}
+
+impl Strategy for SlotRank {
+    fn name(&self) -> &str {
+        "slot_rank"
+    }
+
+    fn rate(&self, items: &mut [Data], _min_dps: f32, _max_dps: f32) {
+        let slots = rank_slots(items);
+
+        for data in items.iter_mut() {
+            let keep = self.rank * positions(&data.slot);
+
+            // the dps of the last item that is kept
+            let last = slots[&data.slot].get(keep - 1).cloned();
+            data.ignored = match last {
+                Some(last) => data.seen >= self.min_seen && data.dps_avg < last,
+                None => false
+            };
+        }
+    }
+}
+
+
+/// The average dps of all items of a slot, the best first.
+fn rank_slots(items: &[Data]) -> HashMap<String, Vec<f32>> {
+    let mut slots: HashMap<String, Vec<f32>> = HashMap::new();
+    for data in items.iter() {
+        slots.entry(data.slot.clone()).or_default().push(data.dps_avg);
+    }
+
+    for dps in slots.values_mut() {
+        dps.sort_by(|a, b| b.partial_cmp(a).unwrap());
+    }
+
+    slots
+}
+
+/// Number of items a slot holds at once.
+fn positions(slot: &str) -> usize {
+    match slot {
+        "finger" | "trinket" | "weaponhand" => 2,
+        _ => 1
+    }
+}
+
+/// Lower confidence bound of the best simulated combination, reduced by
+/// the margin in percent. Every item keeps its best results. So the best
+/// combination is the best result of any item.
+fn best_lower_bound(items: &[Data], z: f32, margin: f32) -> f32 {
+    let mut best: Option<(f32, f32)> = None;
+
+    for data in items.iter() {
+        if let Some(dps) = data.dps_range.first() {
+            if best.is_none_or(|b| *dps > b.0) {
+                best = Some((*dps, data.dps_error.first().cloned().unwrap_or(0.0)));
+            }
+        }
+    }
+
+    match best {
+        Some((dps, error)) => (dps - z * error) * (1.0 - margin / 100.0),
+        None => 0.0
+    }
+}
+
+/// Two sided z score of a confidence level in percent. This uses the
+/// approximation 26.2.23 of Abramowitz and Stegun.
+fn z_score(confidence: f32) -> f32 {
+    if confidence <= 0.0 {
+        return 0.0;
+    }
+
+    let p = ((1.0 - confidence.min(99.99) as f64 / 100.0) / 2.0).max(1e-10);
+    let t = (-2.0 * p.ln()).sqrt();
+
+    (t - (2.515517 + 0.802853 * t + 0.010328 * t * t) /
+        (1.0 + 1.432788 * t + 0.189269 * t * t + 0.001308 * t * t * t)) as f32
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::z_score;
+
+    #[test]
+    fn z_score_of_common_confidences() {
+        assert_eq!(z_score(0.0), 0.0);
+        assert!((z_score(90.0) - 1.645).abs() < 0.01);
+        assert!((z_score(95.0) - 1.960).abs() < 0.01);
+        assert!((z_score(99.0) - 2.576).abs() < 0.01);
+    }
+
+    #[test]
+    fn z_score_grows_with_confidence() {
+        let scores: Vec<f32> = [50.0, 80.0, 95.0, 99.9, 100.0].iter().map(|c| z_score(*c)).collect();
+        assert!(scores.windows(2).all(|w| w[0] < w[1]));
+    }
+}
@@ -3,14 +3,30 @@
 use configuration::Configuration;
 use std::fs::File;
 use serde_json::{from_reader as read_json, Value};
-use std::cell::RefCell;
+use std::sync::Mutex;
 use template::Template;
+use upgrade::Upgrade;
 
 
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Report
 {
     pub html: String,
-    pub dps: f32
+    pub name: String,
+    pub dps: f32,
+    #[serde(default)]
+    pub talents: String,
+    #[serde(default)]
+    pub info: String
+}
+
+
+/// The best reports of a finished stage or talent build.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Stage
+{
+    pub name: String,
+    pub reports: Vec<Report>
 }
 
 
@@ -18,9 +34,13 @@ pub struct Generator
 {
     config: Configuration,
     report_dir: String,
-    reports: RefCell<Vec<Report>>,
+    reports: Mutex<Vec<Report>>,
+    stages: Mutex<Vec<Stage>>,
+    builds: Mutex<Vec<Stage>>,
+    talents: Mutex<String>,
     tpl_report: Template,
-    tpl_list_entry: Template
+    tpl_list_entry: Template,
+    tpl_stage: Template
 }
 
 impl Generator {
@@ -28,6 +48,7 @@ impl Generator {
         // load templates
         let report = Template::load(&format!("{}/{}", &configuration.template_dir, "report.html")).unwrap();
         let list_entry = Template::load(&format!("{}/{}", &configuration.template_dir, "report_list_entry.html")).unwrap();
+        let stage = Template::load(&format!("{}/{}", &configuration.template_dir, "report_stage.html")).unwrap();
 
         report.set_var("best_of", &configuration.simcraft.best_of.to_string()).unwrap();
         report.set_var("report_dir", reports).unwrap();
@@ -36,21 +57,27 @@ impl Generator {
         Generator {
             config: configuration.clone(),
             report_dir: String::from(reports),
-            reports: RefCell::new(Vec::new()),
+            reports: Mutex::new(Vec::new()),
+            stages: Mutex::new(Vec::new()),
+            builds: Mutex::new(Vec::new()),
+            talents: Mutex::new(String::new()),
             tpl_report: report,
-            tpl_list_entry: list_entry
+            tpl_list_entry: list_entry,
+            tpl_stage: stage
         }
     }
 
-    /// Insert a report from simc.
+    /// Insert a report from simc. info describes the simulated items,
+    /// e.g. azerite traits and set bonuses.
     /// 
     /// Returns a tuple with the following values
-    /// (at: usize, dps: f32, min_dps: f32, max_dps: f32)
-    pub fn push(&self, json_report: &str, html_report: &str) -> (usize, f32, f32, f32) {
+    /// (at: usize, dps: f32, min_dps: f32, max_dps: f32, error: f32)
+    ///
+    /// error is the standard deviation of the mean dps.
+    pub fn push(&self, json_report: &str, html_report: &str, info: &str) -> (usize, f32, f32, f32, f32) {
         // read json report
         let fin = File::open(&json_report).unwrap();
         let json: Value = read_json(&fin).unwrap();
-        let mut at: usize = 0;
 
         //println!("Push a new report: {}", &html_report);
 
@@ -59,16 +86,82 @@ impl Generator {
             .to_string()
             .parse::<f32>()
             .unwrap();
+        let error = Generator::error_of(&json["sim"]["players"][0]["collected_data"]["dps"]);
+
+        let mut reports = self.reports.lock().unwrap();
+        let at = self.insert(&mut reports, html_report, "", dps, info);
+
+        let range = Generator::range_of(&reports);
+        (at, dps, range.0, range.1, error)
+    }
+
+    /// Insert a report from simc that contains profilesets. Every name
+    /// is one profileset. An empty name stands for the base actor. There
+    /// is one entry in info for every name.
+    /// 
+    /// Returns a tuple for every name with the following values
+    /// (dps: f32, min_dps: f32, max_dps: f32, error: f32)
+    pub fn push_profilesets(&self, json_report: &str, html_report: &str, names: &[String], info: &[String]) -> Vec<(f32, f32, f32, f32)> {
+        // read json report
+        let fin = File::open(json_report).unwrap();
+        let json: Value = read_json(&fin).unwrap();
+        let mut dps_list: Vec<f32> = Vec::new();
+        let mut errors: Vec<f32> = Vec::new();
+
+        for name in names.iter() {
+            let value = if name.is_empty() {
+                &json["sim"]["players"][0]["collected_data"]["dps"]
+            } else {
+                let results = json["sim"]["profilesets"]["results"].as_array()
+                    .expect("Report contains no profilesets");
+                match results.iter().find(|r| r["name"].as_str() == Some(name.as_str())) {
+                    Some(r) => r,
+                    None => panic!("Profileset {} not found in {}", name, json_report)
+                }
+            };
+
+            dps_list.push(value["mean"].to_string().parse::<f32>().unwrap());
+            errors.push(Generator::error_of(value));
+        }
+
+        let mut reports = self.reports.lock().unwrap();
+        for ((name, dps), info) in names.iter().zip(dps_list.iter()).zip(info.iter()) {
+            self.insert(&mut reports, html_report, name, *dps, info);
+        }
+
+        let range = Generator::range_of(&reports);
+        dps_list.iter().zip(errors.iter()).map(|(dps, error)| (*dps, range.0, range.1, *error)).collect()
+    }
+
+    /// Standard deviation of the mean dps. simc calls it mean_std_dev for
+    /// actors and mean_stddev for profilesets. Without it the error is
+    /// derived from mean_error, which is given for a confidence of 95%.
+    fn error_of(dps: &Value) -> f32 {
+        let std_dev = dps["mean_std_dev"].as_f64()
+            .or_else(|| dps["mean_stddev"].as_f64())
+            .or_else(|| dps["mean_error"].as_f64().map(|e| e / 1.96));
+
+        std_dev.unwrap_or(0.0) as f32
+    }
+
+    /// Add a new report to the sorted list. Only the best ones are stored.
+    ///
+    /// Returns the position of this report.
+    fn insert(&self, reports: &mut Vec<Report>, html_report: &str, name: &str, dps: f32, info: &str) -> usize {
+        let mut at: usize = 0;
+        let talents = self.talents.lock().unwrap().clone();
 
         // add to list
-        if self.reports.borrow().is_empty() {
-            self.reports.borrow_mut().push(Report {
+        if reports.is_empty() {
+            reports.push(Report {
                 html: String::from(html_report),
-                dps
+                name: String::from(name),
+                dps,
+                talents,
+                info: String::from(info)
             });
         } else {
-            // borrow checker sucks :/
-            for i in self.reports.borrow().iter() {
+            for i in reports.iter() {
                 if dps > i.dps {
                     break;
                 }
@@ -77,48 +170,133 @@ impl Generator {
             }
 
             if at < self.config.simcraft.best_of {
-                self.reports.borrow_mut().insert(at, Report {
+                reports.insert(at, Report {
                     html: String::from(html_report),
-                    dps
+                    name: String::from(name),
+                    dps,
+                    talents,
+                    info: String::from(info)
                 });
             }
 
             // limit the number of reports
-            if self.reports.borrow().len() > self.config.simcraft.best_of {
-                self.reports.borrow_mut().pop();
+            if reports.len() > self.config.simcraft.best_of {
+                reports.pop();
             }
         }
 
-        let range = self.min_max_dps();
-        (at, dps, range.0, range.1)
+        at
     }
 
-    pub fn compile(&self) {
+    /// Returns a copy of the current list of reports.
+    pub fn snapshot(&self) -> Vec<Report> {
+        self.reports.lock().unwrap().clone()
+    }
 
-        // entry container
-        let mut entries: String = String::new();
+    /// Replace the list of reports. This is used to continue a run.
+    pub fn restore(&self, reports: &[Report]) {
+        *self.reports.lock().unwrap() = reports.to_vec();
+    }
+
+    /// Move the current list of reports into a finished stage. The next
+    /// stage starts with an empty list.
+    pub fn finish_stage(&self, name: &str) {
+        let mut reports = self.reports.lock().unwrap();
+
+        self.stages.lock().unwrap().push(Stage {
+            name: String::from(name),
+            reports: reports.clone()
+        });
+
+        reports.clear();
+    }
+
+    /// Returns a copy of all finished stages.
+    pub fn stages(&self) -> Vec<Stage> {
+        self.stages.lock().unwrap().clone()
+    }
+
+    /// Replace all finished stages. This is used to continue a run.
+    pub fn restore_stages(&self, stages: &[Stage]) {
+        *self.stages.lock().unwrap() = stages.to_vec();
+    }
+
+    /// Set the talents of the following reports. Every report is tagged
+    /// with them.
+    pub fn set_talents(&self, talents: &str) {
+        *self.talents.lock().unwrap() = String::from(talents);
+    }
+
+    /// Move the current list of reports into a finished talent build. The
+    /// next talent build starts with an empty list.
+    pub fn finish_build(&self, talents: &str) {
+        let mut reports = self.reports.lock().unwrap();
+
+        self.builds.lock().unwrap().push(Stage {
+            name: String::from(talents),
+            reports: reports.clone()
+        });
+
+        reports.clear();
+    }
+
+    /// Returns a copy of all finished talent builds.
+    pub fn builds(&self) -> Vec<Stage> {
+        self.builds.lock().unwrap().clone()
+    }
 
+    /// Replace all finished talent builds. This is used to continue a run.
+    pub fn restore_builds(&self, builds: &[Stage]) {
+        *self.builds.lock().unwrap() = builds.to_vec();
+    }
+
+    /// Compile the report. If several talent builds are simulated, the
+    /// main list holds the best pairs of gear and talents of all builds.
+    /// Every build gets its own list below.
+    pub fn compile(&self) {
         println!("Try to compile the report");
 
-        let range = self.min_max_dps();
+        let builds = self.builds.lock().unwrap();
+        let mut reports: Vec<Report> = self.reports.lock().unwrap().clone();
+        for build in builds.iter() {
+            reports.extend(build.reports.iter().cloned());
+        }
+
+        reports.sort_by(|a, b| b.dps.partial_cmp(&a.dps).unwrap());
+        reports.truncate(self.config.simcraft.best_of);
+
+        let range = Generator::range_of(&reports);
 
         println!("Min DPS: {} / Max DPS: {}", range.0, range.1);
         self.tpl_report.set_var("min_dps", &range.0.to_string()).unwrap();
         self.tpl_report.set_var("max_dps", &range.1.to_string()).unwrap();
 
-        // list all reports
-        for r in self.reports.borrow().iter() {
-            // fill template
-            self.tpl_list_entry.set_var("dps", &(r.dps.round() as i32).to_string()).unwrap();
-            self.tpl_list_entry.set_var("val_now", &(((r.dps / range.1) * 100.0).round() as i32).to_string()).unwrap();
-            self.tpl_list_entry.set_var("html_report_file", &self._get_report_file(&r.html)).unwrap();
-            self.tpl_list_entry.set_var("html_report_name", &self._get_report_name(&r.html)).unwrap();
+        // fill out the basic template
+        let entries = self.compile_entries(&reports, range.1);
+        self.tpl_report.set_var("report_list", &entries).unwrap();
 
-            entries.push_str(&self.tpl_list_entry.compile().unwrap());
+        // list all talent builds and finished stages
+        let mut stages: String = String::new();
+        for build in builds.iter() {
+            let max_dps = Generator::range_of(&build.reports).1;
+
+            println!("Talents {}: Max DPS: {}", build.name, max_dps);
+            self.tpl_stage.set_var("stage_name", &format!("Talents {}", build.name)).unwrap();
+            self.tpl_stage.set_var("stage_info", "The best gear of this talent build.").unwrap();
+            self.tpl_stage.set_var("report_list", &self.compile_entries(&build.reports, max_dps)).unwrap();
+            stages.push_str(&self.tpl_stage.compile().unwrap());
         }
 
-        // fill out the basic template
-        self.tpl_report.set_var("report_list", &entries).unwrap();
+        for stage in self.stages.lock().unwrap().iter() {
+            let max_dps = Generator::range_of(&stage.reports).1;
+
+            self.tpl_stage.set_var("stage_name", &stage.name).unwrap();
+            self.tpl_stage.set_var("stage_info", "The best reports of this stage. Only the best of them are simulated again.").unwrap();
+            self.tpl_stage.set_var("report_list", &self.compile_entries(&stage.reports, max_dps)).unwrap();
+            stages.push_str(&self.tpl_stage.compile().unwrap());
+        }
+
+        self.tpl_report.set_var("stage_list", &stages).unwrap();
 
         // store report
         let store = &format!("{}/{}", self.report_dir, "report.html");
@@ -127,15 +305,96 @@ impl Generator {
         println!("Report: {}", store);
     }
 
-    pub fn min_max_dps(&self) -> (f32, f32) {
+    /// Compile the report of an upgrade search. All candidates are grouped
+    /// by slot and sorted by their difference to the baseline.
+    pub fn compile_upgrades(&self, baseline_dps: f32, baseline_html: &str, upgrades: &[Upgrade]) {
+        println!("Try to compile the upgrade report");
+
+        let tpl_report = Template::load(&format!("{}/{}", &self.config.template_dir, "upgrade.html")).unwrap();
+        let tpl_slot = Template::load(&format!("{}/{}", &self.config.template_dir, "upgrade_slot.html")).unwrap();
+        let tpl_entry = Template::load(&format!("{}/{}", &self.config.template_dir, "upgrade_entry.html")).unwrap();
+
+        tpl_report.set_var("version", ::VERSION).unwrap();
+        tpl_report.set_var("baseline_dps", &(baseline_dps.round() as i32).to_string()).unwrap();
+        tpl_report.set_var("baseline_report_file", &self._get_report_file(baseline_html)).unwrap();
+
+        // keep the order of the slots
+        let mut groups: Vec<&str> = Vec::new();
+        for u in upgrades.iter() {
+            if !groups.contains(&u.group.as_str()) {
+                groups.push(&u.group);
+            }
+        }
+
+        let mut slots: String = String::new();
+        for group in groups.iter() {
+            let mut entries: Vec<&Upgrade> = upgrades.iter().filter(|u| u.group == *group).collect();
+            entries.sort_by(|a, b| b.delta.partial_cmp(&a.delta).unwrap());
+
+            let mut list: String = String::new();
+            for u in entries.iter() {
+                tpl_entry.set_var("item", &u.item).unwrap();
+                tpl_entry.set_var("slot", &u.slot).unwrap();
+                tpl_entry.set_var("dps", &(u.dps.round() as i32).to_string()).unwrap();
+                tpl_entry.set_var("delta", &format!("{:+}", u.delta.round() as i32)).unwrap();
+                tpl_entry.set_var("percent", &format!("{:+.2}", u.percent)).unwrap();
+                tpl_entry.set_var("state", if u.delta > 0.0 { "table-success" } else { "" }).unwrap();
+                tpl_entry.set_var("html_report_file", &self._get_report_file(&u.html)).unwrap();
+
+                list.push_str(&tpl_entry.compile().unwrap());
+            }
+
+            tpl_slot.set_var("slot_name", group).unwrap();
+            tpl_slot.set_var("upgrade_list", &list).unwrap();
+            slots.push_str(&tpl_slot.compile().unwrap());
+        }
+
+        tpl_report.set_var("slot_list", &slots).unwrap();
+
+        // store report
+        let store = format!("{}/{}", self.report_dir, "report.html");
+        Template::store(&store, &tpl_report.compile().unwrap()).unwrap();
+
+        println!("Report: {}", store);
+    }
+
+    /// Compile a list entry for every report.
+    fn compile_entries(&self, reports: &[Report], max_dps: f32) -> String {
+        // entry container
+        let mut entries: String = String::new();
+
+        // list all reports
+        for r in reports.iter() {
+            // fill template
+            self.tpl_list_entry.set_var("dps", &(r.dps.round() as i32).to_string()).unwrap();
+            self.tpl_list_entry.set_var("val_now", &(((r.dps / max_dps) * 100.0).round() as i32).to_string()).unwrap();
+            self.tpl_list_entry.set_var("html_report_file", &self._get_report_file(&r.html)).unwrap();
+            let mut name = self._get_report_name(&r.html);
+            // the report of a profileset shows the base actor of its batch
+            if !r.name.is_empty() {
+                name.push_str(&format!(" ({}, base actor of this batch)", r.name));
+            }
+            if !r.talents.is_empty() {
+                name.push_str(&format!(" - talents {}", r.talents));
+            }
+            self.tpl_list_entry.set_var("html_report_name", &name).unwrap();
+            self.tpl_list_entry.set_var("info", &r.info).unwrap();
+
+            entries.push_str(&self.tpl_list_entry.compile().unwrap());
+        }
+
+        entries
+    }
+
+    fn range_of(reports: &[Report]) -> (f32, f32) {
         // max dps
-        let max_dps: f32 = match self.reports.borrow().first() {
+        let max_dps: f32 = match reports.first() {
             Some(v) => v.dps,
             None => 0.0
         };
 
         // min dps
-        let min_dps: f32 = match self.reports.borrow().last() {
+        let min_dps: f32 = match reports.last() {
             Some(v) => v.dps,
             None => 0.0
         };
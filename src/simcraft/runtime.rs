@@ -0,0 +1,191 @@
+use std::fs::{File, metadata};
+use std::result::{Result};
+use std::io::{Error, ErrorKind};
+use std::sync::Mutex;
+use std::time::{Duration as StdDuration, UNIX_EPOCH};
+use serde_json::{from_reader as read_json, to_string_pretty as write_json};
+use chrono::{Local, Duration};
+use template::Template;
+
+
+/// The measured duration of a simulation. The first value comes from a
+/// calibration run. As soon as simulations are finished, their average
+/// duration is used instead.
+///
+/// The runtime also knows how many combinations of the current stage are
+/// left and how many simulations the following stages and talent builds
+/// are expected to need. So it can tell when the whole run will be finished.
+pub struct Runtime {
+    calibrated: f64,
+    state: Mutex<State>
+}
+
+#[derive(Default)]
+struct State {
+    /// number and duration of all simulated combinations
+    sims: u64,
+    seconds: f64,
+
+    /// combinations of the current stage
+    remaining: u64,
+    taken: u64,
+    simulated: u64,
+
+    /// expected simulations of all following stages and builds
+    later: u64
+}
+
+impl Runtime {
+    pub fn new(seconds: f64) -> Runtime {
+        Runtime {
+            calibrated: seconds,
+            state: Mutex::new(State::default())
+        }
+    }
+
+    /// Average duration of a single combination in seconds.
+    pub fn per_sim(&self) -> f64 {
+        let state = self.state.lock().unwrap();
+        if state.sims == 0 {
+            self.calibrated
+        } else {
+            state.seconds / state.sims as f64
+        }
+    }
+
+    /// Duration of the given number of simulations in seconds.
+    pub fn estimate(&self, sims: u64, jobs: usize) -> u64 {
+        (sims as f64 * self.per_sim() / jobs.max(1) as f64) as u64
+    }
+
+    /// A new stage with the given number of combinations starts. later
+    /// is the number of simulations that are expected after this stage.
+    pub fn start(&self, combinations: u64, later: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.remaining = combinations;
+        state.taken = 0;
+        state.simulated = 0;
+        state.later = later;
+    }
+
+    /// A combination was not simulated. If it is ignored by the statistic,
+    /// it counts for the share of combinations that are really simulated.
+    pub fn skip(&self, ignored: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.remaining = state.remaining.saturating_sub(1);
+        if ignored {
+            state.taken += 1;
+        }
+    }
+
+    /// A simc process has simulated some combinations.
+    pub fn record(&self, combinations: u64, duration: StdDuration) {
+        let mut state = self.state.lock().unwrap();
+        state.remaining = state.remaining.saturating_sub(combinations);
+        state.taken += combinations;
+        state.simulated += combinations;
+        state.sims += combinations;
+        state.seconds += duration.as_secs_f64();
+    }
+
+    /// Remaining time of the run in seconds. Of the current stage only the
+    /// share of combinations that was simulated so far is expected to be
+    /// simulated.
+    pub fn remaining(&self, jobs: usize) -> u64 {
+        let sims = {
+            let state = self.state.lock().unwrap();
+            let current = if state.taken == 0 {
+                state.remaining
+            } else {
+                (state.remaining as f64 * state.simulated as f64 / state.taken as f64).ceil() as u64
+            };
+
+            current + state.later
+        };
+
+        self.estimate(sims, jobs)
+    }
+
+    /// Text for the progress bar.
+    pub fn message(&self, jobs: usize) -> String {
+        let remaining = self.remaining(jobs);
+        format!("ETA {} - finished approximatly at {}",
+            ::fmt_duration(remaining),
+            (Local::now() + Duration::seconds(remaining as i64)).format("%d.%m.%Y - %H:%M:%S"))
+    }
+}
+
+
+/// A cached calibration. It is valid as long as the compiled template and
+/// the simc binary are not changed. The fingerprint covers the compiled
+/// template with all imports, the talents and the options of the first
+/// stage. The template file is only kept to read the cache.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Calibration {
+    pub template: String,
+    pub fingerprint: String,
+    pub executeable: String,
+    pub executeable_modified: u64,
+    pub seconds: f64
+}
+
+impl Calibration {
+    pub fn new(template: &str, fingerprint: &str, executeable: &str, seconds: f64) -> Calibration {
+        Calibration {
+            template: String::from(template),
+            fingerprint: String::from(fingerprint),
+            executeable: String::from(executeable),
+            executeable_modified: Calibration::modified(executeable),
+            seconds
+        }
+    }
+
+    /// Search the cache for a calibration of this fingerprint and binary.
+    pub fn lookup(file: &str, fingerprint: &str, executeable: &str) -> Option<f64> {
+        let current = Calibration::new("", fingerprint, executeable, 0.0);
+
+        Calibration::load(file).iter()
+            .find(|c| c.same(&current))
+            .map(|c| c.seconds)
+    }
+
+    /// Add this calibration to the cache. An older one of the same
+    /// fingerprint and binary is replaced.
+    pub fn store(&self, file: &str) -> Result<(), Error> {
+        let mut cache = Calibration::load(file);
+        cache.retain(|c| !c.same(self));
+        cache.push(self.clone());
+
+        match write_json(&cache) {
+            Ok(json) => Template::store(file, &json),
+            Err(err) => {
+                Err(Error::new(ErrorKind::InvalidData,
+                    format!("Cannot write calibration cache {}: {}", file, err)))
+            }
+        }
+    }
+
+    /// A missing or broken cache is handled like an empty one.
+    fn load(file: &str) -> Vec<Calibration> {
+        match File::open(file) {
+            Ok(json) => read_json(json).unwrap_or_default(),
+            Err(_) => Vec::new()
+        }
+    }
+
+    fn same(&self, other: &Calibration) -> bool {
+        self.fingerprint == other.fingerprint &&
+        self.executeable == other.executeable &&
+        self.executeable_modified == other.executeable_modified
+    }
+
+    /// Time of the last modification in seconds since the epoch.
+    fn modified(file: &str) -> u64 {
+        metadata(file)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
@@ -0,0 +1,187 @@
+use std::result::{Result};
+use std::io::{Error, ErrorKind};
+use std::collections::{HashMap, HashSet};
+use rand::Rng;
+use rand::rngs::StdRng;
+use rand::distributions::StandardNormal;
+use configuration::Scheduler as Config;
+use item::Item;
+use permutation::Iter;
+use statistic::Statistic;
+
+
+/// How the next combination is chosen.
+#[derive(PartialEq, Clone, Copy)]
+pub enum Mode {
+    Random,
+    Ucb,
+    Thompson
+}
+
+impl Mode {
+    pub fn from_str(mode: &str) -> Result<Mode, Error> {
+        match mode {
+            "random" => Ok(Mode::Random),
+            "ucb" => Ok(Mode::Ucb),
+            "thompson" => Ok(Mode::Thompson),
+            mode => {
+                Err(Error::new(ErrorKind::InvalidData,
+                    format!("Unknown scheduler {}. Use random, ucb or thompson", mode)))
+            }
+        }
+    }
+}
+
+
+/// Chooses the next combination like a multi-armed bandit. Every item
+/// variant is an arm and its reward is the dps of the statistic.
+///
+/// The combinations still come in the random order. The scheduler keeps
+/// a pool of them and always returns the one with the most promising
+/// items. So the best combinations are found early, but every combination
+/// is returned once in the end.
+///
+/// Combinations that are already done or have ignored items are skipped
+/// by the stage. So they are returned at once without a score.
+pub struct Scheduler<'a> {
+    source: Iter<'a>,
+    pool: Vec<(u64, Vec<Item>)>,
+    pool_size: usize,
+    statistic: &'a Statistic,
+    finished: HashSet<u64>,
+    mode: Mode,
+    exploration: f64,
+    rng: StdRng,
+    arms: Arms
+}
+
+/// The observed dps of every item variant. It is only read again if the
+/// statistic has changed.
+struct Arms {
+    version: Option<u64>,
+    data: HashMap<String, (f32, f32, i32)>,
+    total: i32,
+    min: f64,
+    range: f64
+}
+
+impl<'a> Scheduler<'a> {
+    pub fn new(source: Iter<'a>, statistic: &'a Statistic, finished: HashSet<u64>, config: &Config, rng: StdRng) -> Result<Scheduler<'a>, Error> {
+        let mode = Mode::from_str(&config.mode)?;
+
+        Ok(Scheduler {
+            source,
+            pool: Vec::new(),
+            pool_size: config.pool.max(1),
+            statistic,
+            finished,
+            mode,
+            exploration: config.exploration as f64,
+            rng,
+            arms: Arms {
+                version: None,
+                data: HashMap::new(),
+                total: 0,
+                min: 0.0,
+                range: 1.0
+            }
+        })
+    }
+
+    /// Read the arms again if the statistic has changed.
+    fn refresh(&mut self) {
+        let version = self.statistic.version();
+        if self.arms.version == Some(version) {
+            return;
+        }
+
+        let data = self.statistic.arms();
+
+        // dps is scaled to the range of all arms
+        let min = data.values().map(|a| a.0 as f64).fold(f64::MAX, f64::min);
+        let max = data.values().map(|a| a.0 as f64).fold(f64::MIN, f64::max);
+
+        self.arms = Arms {
+            version: Some(version),
+            total: data.values().map(|a| a.2).sum(),
+            min,
+            range: if max > min { max - min } else { 1.0 },
+            data
+        };
+    }
+
+    /// Score of an item variant. Items without any simulation are treated
+    /// optimistically: like the best item with the highest uncertainty.
+    fn score(mode: Mode, exploration: f64, arms: &Arms, variant: &str, rng: &mut StdRng) -> f64 {
+        match (arms.data.get(variant), mode) {
+            (Some(arm), Mode::Ucb) => {
+                (arm.0 as f64 - arms.min) / arms.range +
+                    exploration * ((arms.total.max(1) as f64).ln() / arm.2 as f64).sqrt()
+            },
+            (Some(arm), _) => {
+                // a single result has no spread. So there is a minimum uncertainty.
+                let spread = (arm.1 as f64 / arms.range).max(0.1) / (arm.2 as f64).sqrt();
+                let noise: f64 = rng.sample(StandardNormal);
+                (arm.0 as f64 - arms.min) / arms.range + exploration * spread * noise
+            },
+            // half a simulation
+            (None, Mode::Ucb) => 1.0 + exploration * (2.0 * (arms.total.max(2) as f64).ln()).sqrt(),
+            // the spread of the whole range
+            (None, _) => {
+                let noise: f64 = rng.sample(StandardNormal);
+                1.0 + exploration * noise
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Scheduler<'a> {
+    type Item = (u64, Vec<Item>);
+
+    fn next(&mut self) -> Option<(u64, Vec<Item>)> {
+        if self.mode == Mode::Random {
+            return self.source.next();
+        }
+
+        while self.pool.len() < self.pool_size {
+            match self.source.next() {
+                Some(next) => {
+                    if self.finished.contains(&next.0) || self.statistic.has_ignores(&next.1) {
+                        return Some(next);
+                    }
+
+                    self.pool.push(next);
+                },
+                None => break
+            }
+        }
+
+        if self.pool.is_empty() {
+            return None;
+        }
+
+        self.refresh();
+
+        // the combination with the best average score of its items. Every
+        // item variant is scored once.
+        let Scheduler { ref pool, ref arms, ref mut rng, mode, exploration, .. } = *self;
+        let mut scores: HashMap<&str, f64> = HashMap::new();
+        let mut best = 0usize;
+        let mut best_score = f64::MIN;
+        for (n, (_, stack)) in pool.iter().enumerate() {
+            let mut sum = 0.0;
+            for item in stack.iter() {
+                sum += *scores.entry(item.variant())
+                    .or_insert_with(|| Scheduler::score(mode, exploration, arms, item.variant(), rng));
+            }
+
+            let score = sum / stack.len().max(1) as f64;
+            if score > best_score {
+                best = n;
+                best_score = score;
+            }
+        }
+
+        Some(self.pool.swap_remove(best))
+    }
+}
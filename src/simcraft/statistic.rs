@@ -1,81 +1,288 @@
 
 
 use item::Item;
-use std::cell::RefCell;
+use slot::Slot;
+use std::sync::Mutex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::result::{Result};
+use std::io::{Error, ErrorKind};
+use std::fs::{File, rename};
+use serde_json::{from_reader as read_json, to_string as write_json};
 use configuration::Configuration;
+use pruning::{self, Strategy};
+use template::Template;
 
 
-#[derive(Default)]
+pub const STATISTIC_FILE: &str = "statistic.json";
+
+
+/// The collected data of an item variant. Data of older runs has no
+/// variant. It belongs to all variants of the item id.
+#[derive(Serialize, Deserialize, Default, Clone)]
 pub struct Data {
-    item_id: u32,
-    dps_avg: f32,
-    dps_range: Vec<f32>,
+    pub item_id: u32,
+    #[serde(default)]
+    pub variant: String,
+    pub dps_avg: f32,
+    pub dps_range: Vec<f32>,
 //    dps_range: f64,
-    rating: i32,
-    seen: i32
+    pub rating: i32,
+    pub seen: i32,
+    /// standard deviation of the mean dps of every value in dps_range
+    #[serde(default)]
+    pub dps_error: Vec<f32>,
+    /// slot group, e.g. finger for both rings
+    #[serde(default)]
+    pub slot: String,
+    /// set by the pruning strategy
+    #[serde(default)]
+    pub ignored: bool
 }
 
 impl Data {
-    pub fn new(id: u32, dps: f32) -> Data {
+    pub fn new(item: &Item, dps: f32, error: f32) -> Data {
         let mut data = Data {
-            item_id: id,
+            item_id: item.id,
+            variant: item.variant().to_string(),
             dps_avg: dps,
 //            dps_range: dps as f64,
             dps_range: Vec::new(),
             rating: 0,
-            seen: 1
+            seen: 1,
+            dps_error: Vec::new(),
+            slot: Slot::from_enum(Slot::fix_slot(item.slot.slot)).name,
+            ignored: false
         };
 
         data.dps_range.push(dps);
+        data.dps_error.push(error);
 
         data
     }
+
+    /// Does this data belong to the item with the given id and variant?
+    fn is(&self, id: u32, variant: &str) -> bool {
+        if self.variant.is_empty() {
+            self.item_id == id
+        } else {
+            self.variant == variant
+        }
+    }
+
+    /// Standard deviation of dps_avg. Older data has no errors.
+    pub fn error(&self) -> f32 {
+        let sum: f32 = self.dps_error.iter().map(|e| e * e).sum();
+
+        sum.sqrt() / self.dps_range.len().max(1) as f32
+    }
+}
+
+
+/// The statistic of a talent build as it is stored in the output
+/// directory. The fingerprint identifies the template and the talents.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Export {
+    pub fingerprint: String,
+    pub talents: String,
+    pub items: Vec<Data>
 }
 
 
 pub struct Statistic
 {
-    pub items: RefCell<Vec<Data>>,
-    pub tolerance: f32,
-    pub threshold: i32,
+    pub items: Mutex<Vec<Data>>,
     pub iterations: u64,
     pub range_size: usize,
-    pub modifier: f32
+    strategy: Box<dyn Strategy>,
+    skipped: AtomicU64,
+    version: AtomicU64,
+    build: Mutex<(String, String)>
 }
 
 
 impl Statistic {
-    pub fn new(config: &Configuration, iterations: u64, total_items: usize) -> Statistic {
-        let mut obj = Statistic {
-            items: RefCell::new(Vec::new()),
-            tolerance: config.statistic.tolerance,
-            threshold: config.statistic.threshold,
+    /// Create an empty statistic with the configured pruning strategy.
+    pub fn new(config: &Configuration, iterations: u64, total_items: usize) -> Result<Statistic, Error> {
+        Ok(Statistic {
+            items: Mutex::new(Vec::new()),
             iterations,
             range_size: config.simcraft.best_of,
-            modifier: 0.0f32
+            strategy: pruning::create(config, iterations, total_items)?,
+            skipped: AtomicU64::new(0),
+            version: AtomicU64::new(0),
+            build: Mutex::new((String::new(), String::new()))
+        })
+    }
+
+    /// Set the fingerprint and the talents of the following data.
+    pub fn set_build(&self, fingerprint: &str, talents: &str) {
+        *self.build.lock().unwrap() = (String::from(fingerprint), String::from(talents));
+    }
+
+    /// Write the data to the statistic file of the output directory. The
+    /// data of other talent builds in this file is kept.
+    pub fn store(&self, output_dir: &str) -> Result<(), Error> {
+        let file = format!("{}/{}", output_dir, STATISTIC_FILE);
+        let tmp = format!("{}.tmp", file);
+        let (fingerprint, talents) = self.build.lock().unwrap().clone();
+
+        let mut exports: Vec<Export> = Statistic::load(output_dir).unwrap_or_default();
+        exports.retain(|e| e.fingerprint != fingerprint);
+        exports.push(Export {
+            fingerprint,
+            talents,
+            items: self.snapshot()
+        });
+
+        let json = match write_json(&exports) {
+            Ok(json) => json,
+            Err(err) => {
+                return Err(Error::new(ErrorKind::InvalidData,
+                    format!("Cannot write statistic: {}", err)));
+            }
+        };
+
+        Template::store(&tmp, &json)?;
+        rename(&tmp, &file)
+    }
+
+    /// Read the statistic file of an output directory.
+    pub fn load(output_dir: &str) -> Result<Vec<Export>, Error> {
+        let file = format!("{}/{}", output_dir, STATISTIC_FILE);
+
+        match File::open(&file) {
+            Ok(json) => {
+                match read_json(json) {
+                    Ok(exports) => Ok(exports),
+                    Err(err) => {
+                        Err(Error::new(ErrorKind::InvalidData,
+                            format!("Cannot read statistic {}: {}", file, err)))
+                    }
+                }
+            },
+            Err(err) => {
+                Err(Error::new(ErrorKind::NotFound,
+                    format!("Failed to open statistic {}: {:?}", file, err)))
+            }
+        }
+    }
+
+    /// Start with the data of an earlier run. It is only used if it has
+    /// the same fingerprint. decay weakens the old data: every item counts
+    /// as seen less often and its error grows. So new results win soon.
+    ///
+    /// Returns the number of imported items. None if there is no data
+    /// with the same fingerprint.
+    pub fn warm_start(&self, run_dir: &str, decay: f32) -> Result<Option<usize>, Error> {
+        let fingerprint = self.build.lock().unwrap().0.clone();
+        let export = match Statistic::load(run_dir)?.into_iter().find(|e| e.fingerprint == fingerprint) {
+            Some(export) => export,
+            None => return Ok(None)
         };
 
-        obj.modifier = ((iterations as f64).sqrt() / (total_items as f64 / 16.0)) as f32;
-        //println!("Using modifier: {}", obj.modifier);
+        let decay = decay.clamp(0.0, 1.0);
+        let mut items: Vec<Data> = Vec::new();
+        for mut data in export.items.into_iter() {
+            data.seen = (data.seen as f32 * decay).round() as i32;
+            if data.seen == 0 || data.dps_range.is_empty() {
+                continue;
+            }
+
+            for error in data.dps_error.iter_mut() {
+                *error /= decay.sqrt();
+            }
+
+            items.push(data);
+        }
+
+        // rate the items with the range of the best old results
+        let mut best: Vec<f32> = items.iter().flat_map(|d| d.dps_range.iter().cloned()).collect();
+        best.sort_by(|a, b| b.partial_cmp(a).unwrap());
+        best.dedup();
+        best.truncate(self.range_size.max(1));
+
+        if let (Some(max_dps), Some(min_dps)) = (best.first(), best.last()) {
+            self.strategy.rate(&mut items, *min_dps, *max_dps);
+        }
 
-        obj
+        let count = items.len();
+        self.restore(&items);
+
+        Ok(Some(count))
     }
 
-    pub fn update(&self, stack: &[Item], dps: f32, min_dps: f32, max_dps: f32) {
+    /// Name of the pruning strategy.
+    pub fn strategy(&self) -> &str {
+        self.strategy.name()
+    }
+
+    /// A combination was skipped because of the statistic.
+    pub fn skip(&self) {
+        self.skipped.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Number of skipped combinations.
+    pub fn skipped(&self) -> u64 {
+        self.skipped.load(Ordering::SeqCst)
+    }
+
+    /// Changes with every change of the data.
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::SeqCst)
+    }
+
+    /// Add the result of a simulation. error is the standard deviation
+    /// of the mean dps reported by simc.
+    pub fn update(&self, stack: &[Item], dps: f32, error: f32, min_dps: f32, max_dps: f32) {
+        let mut items = self.items.lock().unwrap();
+
         for i in stack.iter() {
-            self._add_new_one(i, dps);
+            self._add_new_one(&mut items, i, dps, error);
         }
 
         // rate all items
-        for data in self.items.borrow_mut().iter_mut() {
-            self._rate_item(data, dps, min_dps, max_dps);
-        }
+        self.strategy.rate(&mut items, min_dps, max_dps);
+        self.version.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// The observed dps of every item variant. Returns a tuple with
+    /// (dps_avg, spread of the best results, seen) for every variant.
+    pub fn arms(&self) -> HashMap<String, (f32, f32, i32)> {
+        let items = self.items.lock().unwrap();
+
+        items.iter()
+            .filter(|d| !d.variant.is_empty())
+            .map(|d| {
+                let n = d.dps_range.len().max(1) as f32;
+                let variance = d.dps_range.iter().map(|x| (x - d.dps_avg).powi(2)).sum::<f32>() / n;
+                (d.variant.clone(), (d.dps_avg, variance.sqrt(), d.seen))
+            })
+            .collect()
+    }
+
+    /// Returns a copy of all collected data.
+    pub fn snapshot(&self) -> Vec<Data> {
+        self.items.lock().unwrap().clone()
+    }
+
+    /// Replace all collected data. This is used to continue a run.
+    pub fn restore(&self, data: &[Data]) {
+        *self.items.lock().unwrap() = data.to_vec();
+        self.version.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Is this item ignored by the pruning strategy?
+    pub fn ignores(&self, item: &Item) -> bool {
+        self.has_ignores(std::slice::from_ref(item))
     }
 
     pub fn has_ignores(&self, stack: &[Item]) -> bool {
+        let items = self.items.lock().unwrap();
+
         for i in stack.iter() {
-            for d in self.items.borrow().iter() {
-                if i.id == d.item_id && d.rating < self.threshold {
+            for d in items.iter() {
+                if d.is(i.id, i.variant()) && d.ignored {
                     return true;
                 }
             }
@@ -95,34 +302,18 @@ impl Statistic {
     }
 */
     // add a new item to the directory
-    fn _add_new_one(&self, item: &Item, dps: f32) {
+    fn _add_new_one(&self, items: &mut Vec<Data>, item: &Item, dps: f32, error: f32) {
         // check for existing one
-        for data in self.items.borrow_mut().iter_mut() {
-            if data.item_id == item.id {
+        for data in items.iter_mut() {
+            if data.is(item.id, item.variant()) {
                 // calcluate avg dps
-                self._calc_avg_dps(data, dps);
+                self._calc_avg_dps(data, dps, error);
                 return;
             }
         }
 
         // add new one
-        self.items.borrow_mut().push(Data::new(item.id, dps));
-    }
-
-    fn _rate_item(&self, data: &mut Data, _dps: f32, min_dps: f32, max_dps: f32) {
-        // could be negativ. So, we need more then the minimum dps.
-        let mut tolerance = (1.0 + (self.tolerance / 100.0)) + (1.0 - (data.seen as f32 / self.modifier)).sin();
-        if tolerance < 1.0 {
-            tolerance = 1.0;
-        }
-
-        // calc minimum dps with tolerance
-        let min = min_dps / tolerance;
-
-        // rate item
-        data.rating = ((data.dps_avg - min) / (max_dps - min) * 100.0) as i32;
-        //println!("Rating: Item({}) -> Rating({}) / DPS({} / {}) Avg DPS({}) | Min({}), Seen({}), Tol({})", 
-        //    data.item_id, data.rating, min_dps, max_dps, data.dps_avg, min, data.seen, tolerance);
+        items.push(Data::new(item, dps, error));
     }
 
 /*
@@ -138,15 +329,19 @@ impl Statistic {
     }
 */
 
-    fn _calc_avg_dps(&self, data: &mut Data, dps: f32) {
+    fn _calc_avg_dps(&self, data: &mut Data, dps: f32, error: f32) {
         data.seen += 1;
 
-        data.dps_range.push(dps);
-        data.dps_range.sort_by(|a, b| b.partial_cmp(a).unwrap());
-        
-        if data.dps_range.len() > self.range_size {
-            data.dps_range.pop();
-        }
+        // keep the errors next to their dps. Older data has no errors.
+        let mut range: Vec<(f32, f32)> = data.dps_range.iter().cloned()
+            .zip(data.dps_error.iter().cloned().chain(std::iter::repeat(0.0)))
+            .collect();
+        range.push((dps, error));
+        range.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+        range.truncate(self.range_size.max(1));
+
+        data.dps_range = range.iter().map(|r| r.0).collect();
+        data.dps_error = range.iter().map(|r| r.1).collect();
 
         let mut sum: f32 = 0.0;
         for i in data.dps_range.iter() {
@@ -0,0 +1,146 @@
+use item::Item;
+use slot::{Slot, ESlot};
+use super::Simcraft;
+
+
+/// A single profile of the upgrade search. The baseline is the only
+/// profile without a candidate.
+pub struct Profile {
+    pub slot: Slot,
+    pub candidate: Option<Item>,
+    pub stack: Vec<Item>
+}
+
+
+/// The result of a single candidate compared to the baseline.
+pub struct Upgrade {
+    pub group: String,
+    pub slot: String,
+    pub item: String,
+    pub html: String,
+    pub dps: f32,
+    pub delta: f32,
+    pub percent: f32
+}
+
+
+/// A slot of the baseline. Rings, trinkets and weapons have two of them.
+struct Position<'a> {
+    slot: Slot,
+    item: Option<Item>,
+    items: &'a Vec<Item>
+}
+
+
+/// Build all profiles of the upgrade search. The first one is the
+/// baseline with all equipped items. Every other profile swaps a single
+/// item from the bag into its slot. Items of rings, trinkets and weapons
+/// are tested on both positions.
+pub fn profiles(simc: &Simcraft) -> Vec<Profile> {
+    let mut positions: Vec<Position> = Vec::new();
+    let mut slot = Some(ESlot::Head);
+
+    // collect the equipped items of every slot
+    while let Some(s) = slot {
+        if let Some(items) = simc.items.get_slot(s) {
+            let equipped: Vec<&Item> = items.iter().filter(|i| i.equipped).collect();
+            let double = s == ESlot::Finger || s == ESlot::Trinket || s == ESlot::WeaponHand;
+            let parts: Vec<u8> = if double { vec![1, 2] } else { vec![0] };
+
+            for (n, part) in parts.iter().enumerate() {
+                positions.push(Position {
+                    slot: Slot::get_real_slot(&Slot::from_enum(s), *part).unwrap(),
+                    item: equipped.get(n).map(|i| (*i).clone()),
+                    items
+                });
+            }
+        }
+
+        slot = Simcraft::next_slot(s);
+    }
+
+    let mut profiles: Vec<Profile> = Vec::new();
+    profiles.push(Profile {
+        slot: Slot::new(),
+        candidate: None,
+        stack: build_stack(&positions, None)
+    });
+
+    // swap every item of the bag into each position of its slot
+    for (n, position) in positions.iter().enumerate() {
+        for candidate in position.items.iter().filter(|i| !i.equipped) {
+            // cannot add the same item on both slots
+            let twice = positions.iter().enumerate().any(|(m, p)| {
+                m != n && p.slot.slot != position.slot.slot &&
+                Slot::fix_slot(p.slot.slot) == Slot::fix_slot(position.slot.slot) &&
+                match p.item {
+                    Some(ref i) => !simc.can_pair(i, candidate),
+                    None => false
+                }
+            });
+
+            if twice || !fits_weapon(simc, &positions, position, candidate) {
+                continue;
+            }
+
+            let mut stack = build_stack(&positions, Some((n, candidate)));
+            if simc.rules.breaks(&stack) {
+                continue;
+            }
+
+            // a two-handed weapon occupies the off hand, too
+            if simc.is_two_handed(candidate) && position.slot.slot == ESlot::WeaponHand1 {
+                stack.retain(|i| i.slot.slot != ESlot::WeaponHand2);
+            }
+
+            profiles.push(Profile {
+                slot: position.slot.clone(),
+                candidate: Some(candidate.clone()),
+                stack
+            });
+        }
+    }
+
+    profiles
+}
+
+
+/// Check the weapon types of a candidate. Off-hands are only used in
+/// the off hand and two-handed weapons only in the main hand. Nothing
+/// fits in the off hand while a two-handed weapon is equipped.
+fn fits_weapon(simc: &Simcraft, positions: &[Position], position: &Position, candidate: &Item) -> bool {
+    match position.slot.slot {
+        ESlot::WeaponHand1 => !simc.is_off_hand(candidate),
+        ESlot::WeaponHand2 => {
+            let main_is_two_handed = positions.iter()
+                .filter(|p| p.slot.slot == ESlot::WeaponHand1)
+                .any(|p| match p.item {
+                    Some(ref i) => simc.is_two_handed(i),
+                    None => false
+                });
+
+            !simc.is_two_handed(candidate) && !main_is_two_handed
+        },
+        _ => true
+    }
+}
+
+
+fn build_stack(positions: &[Position], swap: Option<(usize, &Item)>) -> Vec<Item> {
+    let mut stack: Vec<Item> = Vec::new();
+
+    for (n, position) in positions.iter().enumerate() {
+        let item = match swap {
+            Some((m, candidate)) if m == n => Some(candidate),
+            _ => position.item.as_ref()
+        };
+
+        if let Some(item) = item {
+            let mut item = item.clone();
+            item.slot = position.slot.clone();
+            stack.push(item);
+        }
+    }
+
+    stack
+}
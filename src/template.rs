@@ -5,13 +5,13 @@ use std::io::prelude::*;
 use std::result::{Result};
 use std::io::{Error, ErrorKind};
 use regex::Regex;
-use std::cell::RefCell;
+use std::sync::Mutex;
 
 #[derive(Default)]
 pub struct Template {
     file: String,
     data: String,
-    variables: RefCell<HashMap<String, String>>,
+    variables: Mutex<HashMap<String, String>>,
     imports: Vec<Template>
 }
 
@@ -33,7 +33,7 @@ impl Template {
                 let mut tpl = Template {
                     file: String::from(file),
                     data: tmpl,
-                    variables: RefCell::new(HashMap::new()),
+                    variables: Mutex::new(HashMap::new()),
                     imports: Vec::new()
                 };
 
@@ -61,17 +61,11 @@ impl Template {
     }
 
     pub fn var_exist(&self, var: &str) -> bool {
-        for (key, _value) in self.variables.borrow_mut().iter() {
-            if key == var {
-                return true;
-            }
-        }
-
-        return false;
+        self.variables.lock().unwrap().contains_key(var)
     }
 
     pub fn set_var(&self, var: &str, value: &str) -> Result<(), Error> {
-        match self.variables.borrow_mut().get_mut(var) {
+        match self.variables.lock().unwrap().get_mut(var) {
             Some(v) => {
                 *v = String::from(value);
                 Ok(())
@@ -83,6 +77,14 @@ impl Template {
     }
 
     pub fn compile(&self) -> Result<String, Error> {
+        self.compile_with(&HashMap::new())
+    }
+
+    /// Compile the template with a set of local variables. These will
+    /// be used instead of the values stored by Template::set_var. Nothing
+    /// of the template is changed. So this can be called from many threads
+    /// at the same time.
+    pub fn compile_with(&self, local: &HashMap<String, String>) -> Result<String, Error> {
         let mut tpl: String = self.data.clone();
 
         // insert imports
@@ -91,7 +93,12 @@ impl Template {
         }
 
         // replace variables
-        for (var, value) in self.variables.borrow_mut().iter() {
+        for (var, value) in self.variables.lock().unwrap().iter() {
+            let value = match local.get(var) {
+                Some(v) => v,
+                None => value
+            };
+
             tpl = tpl.replace(&format!("#[[var={}]]", var), value);
         }
 
@@ -115,12 +122,12 @@ impl Template {
     fn aquire_variables(&self) {
         let regex = Regex::new("#\\[\\[var=([a-z0-9_-]+)\\]\\]").unwrap();
         for i in regex.captures_iter(&self.data) {
-            self.variables.borrow_mut().insert(String::from(&i[1]), String::new());
+            self.variables.lock().unwrap().insert(String::from(&i[1]), String::new());
         }
 
         // aquire variables from imported templates
         for tpl in &self.imports {
-                self.variables.borrow_mut().extend(tpl.variables.borrow_mut().clone());
+                self.variables.lock().unwrap().extend(tpl.variables.lock().unwrap().clone());
         }
     }
 